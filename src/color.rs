@@ -0,0 +1,201 @@
+use clap::ValueEnum;
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// How many colors the terminal can show. Truecolor themes get downsampled to fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+pub enum ColorDepth {
+    #[value(name = "truecolor", alias = "24bit")]
+    #[serde(rename = "truecolor", alias = "24bit")]
+    TrueColor,
+    #[value(name = "256")]
+    #[serde(rename = "256")]
+    Ansi256,
+    #[value(name = "16")]
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Guesses the depth from `COLORTERM` and `TERM`, the same variables most terminal
+    /// programs look at. tmux without `Tc` doesn't set `COLORTERM`, so it lands on 256.
+    pub fn detect() -> ColorDepth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+
+        let term = std::env::var("TERM").unwrap_or_default();
+        if term.contains("truecolor") || term.contains("direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// Converts an RGB color into the closest one this depth can display
+    pub fn rgb(self, r: u8, g: u8, b: u8) -> Color {
+        match self {
+            ColorDepth::TrueColor => Color::Rgb(r, g, b),
+            ColorDepth::Ansi256 => Color::Indexed(rgb_to_256(r, g, b)),
+            ColorDepth::Ansi16 => rgb_to_16(r, g, b),
+        }
+    }
+
+    /// Fits a color from some other source, such as ANSI codes, to this depth
+    pub fn adapt(self, color: Color) -> Color {
+        match (self, color) {
+            (_, Color::Rgb(r, g, b)) => self.rgb(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(idx)) if idx >= 16 => {
+                let (r, g, b) = indexed_to_rgb(idx);
+                rgb_to_16(r, g, b)
+            }
+            _ => color,
+        }
+    }
+}
+
+/// RGB value of an xterm 256 color palette entry from the cube or grayscale ramp
+fn indexed_to_rgb(idx: u8) -> (u8, u8, u8) {
+    if idx >= 232 {
+        let gray = 8 + (idx - 232) * 10;
+        return (gray, gray, gray);
+    }
+    let cube = idx.saturating_sub(16);
+    (
+        CUBE_STEPS[(cube / 36) as usize],
+        CUBE_STEPS[(cube / 6 % 6) as usize],
+        CUBE_STEPS[(cube % 6) as usize],
+    )
+}
+
+/// Channel values used by the 6x6x6 color cube in the xterm palette
+const CUBE_STEPS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_step = |c: u8| {
+        CUBE_STEPS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, step)| (**step as i32 - c as i32).abs())
+            .map(|(i, _)| i as u8)
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest_step(r), nearest_step(g), nearest_step(b));
+    let cube_idx = 16 + 36 * ri + 6 * gi + bi;
+    let cube_rgb = (
+        CUBE_STEPS[ri as usize],
+        CUBE_STEPS[gi as usize],
+        CUBE_STEPS[bi as usize],
+    );
+
+    // The grayscale ramp goes 8, 18, ..., 238 and is often closer for muted colors
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_step = (avg.saturating_sub(8) / 10).min(23) as u8;
+    let gray_val = 8 + gray_step * 10;
+    let gray_idx = 232 + gray_step;
+
+    if distance((r, g, b), (gray_val, gray_val, gray_val)) < distance((r, g, b), cube_rgb) {
+        gray_idx
+    } else {
+        cube_idx
+    }
+}
+
+/// The standard 16 color palette as xterm renders it
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+fn rgb_to_16(r: u8, g: u8, b: u8) -> Color {
+    ANSI_16
+        .iter()
+        .min_by_key(|(_, rgb)| distance((r, g, b), *rgb))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::Reset)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truecolor_is_kept() {
+        assert_eq!(ColorDepth::TrueColor.rgb(1, 2, 3), Color::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn downsamples_to_the_256_color_cube_and_gray_ramp() {
+        let depth = ColorDepth::Ansi256;
+        assert_eq!(depth.rgb(255, 0, 0), Color::Indexed(196));
+        assert_eq!(depth.rgb(0, 0, 0), Color::Indexed(16));
+        assert_eq!(depth.rgb(255, 255, 255), Color::Indexed(231));
+        // base16-ocean's background is closer to a gray than to any cube color
+        assert_eq!(depth.rgb(43, 48, 59), Color::Indexed(236));
+        assert_eq!(depth.rgb(128, 128, 128), Color::Indexed(244));
+    }
+
+    #[test]
+    fn downsamples_to_the_16_color_palette() {
+        let depth = ColorDepth::Ansi16;
+        assert_eq!(depth.rgb(250, 10, 10), Color::LightRed);
+        assert_eq!(depth.rgb(190, 0, 0), Color::Red);
+        assert_eq!(depth.rgb(20, 20, 20), Color::Black);
+        assert_eq!(depth.rgb(100, 100, 255), Color::LightBlue);
+    }
+
+    #[test]
+    fn adapts_indexed_colors_only_when_they_dont_fit() {
+        assert_eq!(
+            ColorDepth::Ansi16.adapt(Color::Indexed(196)),
+            Color::LightRed
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.adapt(Color::Indexed(3)),
+            Color::Indexed(3)
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.adapt(Color::Indexed(244)),
+            Color::DarkGray
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.adapt(Color::Indexed(196)),
+            Color::Indexed(196)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.adapt(Color::Rgb(255, 0, 0)),
+            Color::Indexed(196)
+        );
+        assert_eq!(ColorDepth::TrueColor.adapt(Color::Red), Color::Red);
+    }
+
+    #[test]
+    fn palette_entries_round_trip() {
+        for idx in 16..=255 {
+            let (r, g, b) = indexed_to_rgb(idx);
+            assert_eq!(rgb_to_256(r, g, b), idx, "index {idx}");
+        }
+    }
+}
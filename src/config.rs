@@ -0,0 +1,68 @@
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::Result;
+use crate::color::ColorDepth;
+
+/// User settings read from `$XDG_CONFIG_HOME/findr/config.toml`.
+/// Every field is optional, command line flags take priority over these.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name of the syntect theme used for previews
+    pub theme: Option<String>,
+    /// Overrides color depth detection: "truecolor", "256" or "16"
+    pub color: Option<ColorDepth>,
+    /// Show line numbers next to previewed files, on by default
+    pub line_numbers: Option<bool>,
+    /// Start with long preview lines wrapped, off by default
+    pub wrap: Option<bool>,
+    /// Columns a tab expands to in previews, 4 by default
+    pub tab_width: Option<usize>,
+    /// Preview modified files in a git repo as a diff against HEAD, on by default
+    pub git_diff: Option<bool>,
+    /// Start with the file metadata header shown above previews, off by default
+    pub show_metadata: Option<bool>,
+    /// Render ANSI colors in `.log`/`.ansi` files, on by default
+    pub interpret_ansi: Option<bool>,
+    /// Pretty-print JSON, show CSV/TSV as tables and render Markdown, on by default
+    pub structured_previews: Option<bool>,
+    /// Depth JSON previews start folded at, fully expanded if unset
+    pub collapse_depth: Option<usize>,
+    /// Memory the preview cache may use, in megabytes, 64 by default
+    pub preview_cache_mb: Option<usize>,
+    /// Glob patterns mapped to the syntax files matching them are highlighted with,
+    /// e.g. `"Jenkinsfile*" = "Groovy"`. The first pattern written wins.
+    pub syntax_map: IndexMap<String, String>,
+    /// Commands previewing files by extension, e.g. `md = "glow -s dark {}"`
+    pub preview_commands: BTreeMap<String, String>,
+    /// Milliseconds a preview command may run before it is killed, 3000 by default
+    pub preview_timeout_ms: Option<u64>,
+}
+
+impl Config {
+    /// Reads the config file, falling back to defaults if there isn't one
+    pub fn load() -> Result<Config> {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Config::default());
+        };
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let raw = std::fs::read_to_string(&path)?;
+        toml::from_str(&raw).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+}
+
+/// Where findr looks for `config.toml`, extra `themes/` and `syntaxes/`
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("findr"))
+}
+
+/// Where findr keeps binary dumps of the loaded themes and syntaxes
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("findr"))
+}
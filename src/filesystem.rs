@@ -1,7 +1,48 @@
 use crate::Result;
+use crate::git::{GitFilter, collect_git_files};
+use crate::preview::archive::{ArchiveKind, member_paths};
 use ignore::WalkBuilder;
 use rayon::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Where the candidate files come from: a walk of the directory tree, or the files in
+/// some git state when any of the `--git-*` flags are given
+#[derive(Debug, Clone, Default)]
+pub struct FileSource {
+    pub git: GitFilter,
+    /// List the files inside archives as candidates too
+    pub search_archives: bool,
+}
+
+impl FileSource {
+    pub fn collect(&self, starting_path: &str) -> Result<Vec<PathBuf>> {
+        let files = if self.git.is_active() {
+            collect_git_files(Path::new(starting_path), &self.git)?
+        } else {
+            collect_files(starting_path, true)?
+        };
+        if self.search_archives {
+            Ok(expand_archives(files))
+        } else {
+            Ok(files)
+        }
+    }
+}
+
+/// Adds the members of every archive in `files` right after the archive itself, under
+/// virtual paths like `release.tar.gz!/bin/tool.conf`
+fn expand_archives(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    files
+        .into_par_iter()
+        .flat_map_iter(|path| {
+            let members = match ArchiveKind::from_path(&path) {
+                Some(kind) if path.is_file() => member_paths(&path, kind),
+                _ => Vec::new(),
+            };
+            std::iter::once(path).chain(members)
+        })
+        .collect()
+}
 
 pub fn collect_files(starting_path: &str, toggle_hidden: bool) -> Result<Vec<PathBuf>> {
     // let mut file_vec = Vec::new();
@@ -31,8 +72,51 @@ pub fn collect_files(starting_path: &str, toggle_hidden: bool) -> Result<Vec<Pat
 }
 
 /// Deprecated function, used in tandem with walkdir
-fn is_hidden(path: &PathBuf) -> bool {
+#[allow(dead_code)]
+fn is_hidden(path: &Path) -> bool {
     path.file_name()
         .map(|name| name.to_string_lossy().starts_with("."))
         .unwrap_or(false)
 }
+
+/// Formats a byte count the way `ls -h` does
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}{}", UNITS[0])
+    } else {
+        format!("{size:.1}{}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir_with;
+
+    #[test]
+    fn archive_members_follow_their_archive() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        builder
+            .append_data(&mut header, "inner.txt", &b"x"[..])
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+        let dir = temp_dir_with(&[("a.tar", &tar), ("b.txt", b"")]);
+        let files = vec![dir.path().join("a.tar"), dir.path().join("b.txt")];
+
+        let expanded = expand_archives(files);
+        let member = PathBuf::from(format!("{}!/inner.txt", dir.path().join("a.tar").display()));
+        assert_eq!(
+            expanded,
+            [dir.path().join("a.tar"), member, dir.path().join("b.txt")]
+        );
+    }
+}
@@ -0,0 +1,529 @@
+use git2::{Commit, DiffFormat, DiffOptions, Oid, Repository, Status, StatusOptions};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+
+/// Diffs longer than this are cut off, the pane can't show more than a screenful anyway
+const MAX_DIFF_LINES: usize = 1000;
+
+/// One line of a unified diff, tagged with what kind of line it is
+pub struct DiffLine {
+    /// `+`, `-` or ` ` for content, `H` for hunk headers and `F` for file headers,
+    /// as libgit2 reports the line origin
+    pub origin: char,
+    pub content: String,
+}
+
+/// Opens the repository containing `path`, along with `path` relative to its work tree
+pub fn open_repo(path: &Path) -> Option<(Repository, PathBuf)> {
+    let absolute = std::fs::canonicalize(path).ok()?;
+    let repo = Repository::discover(absolute.parent()?).ok()?;
+    let workdir = std::fs::canonicalize(repo.workdir()?).ok()?;
+    let relative = absolute.strip_prefix(&workdir).ok()?.to_path_buf();
+    Some((repo, relative))
+}
+
+/// The uncommitted changes to `path`, staged and unstaged together, as a diff against
+/// HEAD. `None` if the file isn't in a repository or has no changes.
+pub fn diff_against_head(path: &Path) -> Option<Vec<DiffLine>> {
+    let (repo, relative) = open_repo(path)?;
+    // An unborn branch has no HEAD yet, so everything is diffed against an empty tree
+    let head_tree = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+
+    let mut opts = DiffOptions::new();
+    opts.pathspec(&relative).disable_pathspec_match(true);
+    let diff = repo
+        .diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut opts))
+        .ok()?;
+    if diff.deltas().len() == 0 {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    // Stopping early at MAX_DIFF_LINES makes print report an error, which is expected
+    let _ = diff.print(DiffFormat::Patch, |_, _, line| {
+        let content = String::from_utf8_lossy(line.content())
+            .trim_end_matches(['\n', '\r'])
+            .to_string();
+        for content in content.split('\n') {
+            lines.push(DiffLine {
+                origin: line.origin(),
+                content: content.to_string(),
+            });
+        }
+        lines.len() < MAX_DIFF_LINES
+    });
+
+    Some(lines)
+}
+
+/// Restricts candidates to files in a particular git state, instead of walking the tree
+#[derive(Debug, Clone, Default)]
+pub struct GitFilter {
+    /// Files with staged or unstaged changes, including newly added ones
+    pub modified: bool,
+    pub untracked: bool,
+    /// Every file in the index
+    pub tracked: bool,
+    /// Files changed between the merge base with this revision and the work tree
+    pub changed_since: Option<String>,
+}
+
+impl GitFilter {
+    pub fn is_active(&self) -> bool {
+        self.modified || self.untracked || self.tracked || self.changed_since.is_some()
+    }
+}
+
+/// Lists the files under `root` matching any of the filter's conditions, with paths
+/// shaped like the ones `collect_files` returns for the same root
+pub fn collect_git_files(root: &Path, filter: &GitFilter) -> Result<Vec<PathBuf>> {
+    let (repo, root_in_repo) = open_root(root).ok_or_else(|| {
+        format!(
+            "{} is not inside a git repository with a work tree",
+            root.display()
+        )
+    })?;
+
+    let mut relative: BTreeSet<PathBuf> = BTreeSet::new();
+
+    if filter.modified || filter.untracked {
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(filter.untracked)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        for entry in repo.statuses(Some(&mut opts))?.iter() {
+            let status = entry.status();
+            let wanted = (filter.untracked && status.is_wt_new())
+                || (filter.modified && (is_modified(status) || status.is_index_new()));
+            if wanted && let Some(path) = entry.path() {
+                relative.insert(PathBuf::from(path));
+            }
+        }
+    }
+
+    if filter.tracked {
+        for entry in repo.index()?.iter() {
+            relative.insert(PathBuf::from(
+                String::from_utf8_lossy(&entry.path).into_owned(),
+            ));
+        }
+    }
+
+    if let Some(rev) = &filter.changed_since {
+        let base = repo.revparse_single(rev)?.peel_to_commit()?;
+        let head = repo.head()?.peel_to_commit()?;
+        let merge_base = repo.find_commit(repo.merge_base(base.id(), head.id())?)?;
+        let diff = repo.diff_tree_to_workdir_with_index(Some(&merge_base.tree()?), None)?;
+        for delta in diff.deltas() {
+            if let Some(path) = delta.new_file().path() {
+                relative.insert(path.to_path_buf());
+            }
+        }
+    }
+
+    Ok(relative
+        .into_iter()
+        .filter_map(|path| Some(root.join(path.strip_prefix(&root_in_repo).ok()?)))
+        // Deleted files still show up in statuses and diffs
+        .filter(|path| path.symlink_metadata().is_ok())
+        .collect())
+}
+
+/// Single character status markers for files under `root`, keyed by the same paths
+/// `collect_files` would return. Files without changes are left out.
+pub fn status_markers(root: &Path) -> Option<HashMap<PathBuf, char>> {
+    let (repo, root_in_repo) = open_root(root)?;
+
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(true)
+        .recurse_ignored_dirs(false);
+
+    let statuses = repo.statuses(Some(&mut opts)).ok()?;
+    let markers = statuses
+        .iter()
+        .filter_map(|entry| {
+            // Untracked and ignored directories are reported with a trailing slash
+            let path = Path::new(entry.path()?.trim_end_matches('/'));
+            let path = root.join(path.strip_prefix(&root_in_repo).ok()?);
+            Some((path, status_marker(entry.status())?))
+        })
+        .collect();
+    Some(markers)
+}
+
+/// The letter `git status --short` would use for the most important part of `status`
+fn status_marker(status: Status) -> Option<char> {
+    if status.is_conflicted() {
+        Some('U')
+    } else if status.is_index_new() {
+        Some('A')
+    } else if status.is_index_renamed() || status.is_wt_renamed() {
+        Some('R')
+    } else if status.is_index_deleted() || status.is_wt_deleted() {
+        Some('D')
+    } else if is_modified(status) {
+        Some('M')
+    } else if status.is_wt_new() {
+        Some('?')
+    } else if status.is_ignored() {
+        Some('!')
+    } else {
+        None
+    }
+}
+
+fn is_modified(status: Status) -> bool {
+    status.intersects(
+        Status::INDEX_MODIFIED
+            | Status::INDEX_TYPECHANGE
+            | Status::INDEX_RENAMED
+            | Status::WT_MODIFIED
+            | Status::WT_TYPECHANGE
+            | Status::WT_RENAMED,
+    )
+}
+
+/// Opens the repository containing the directory `root`, along with where `root` sits
+/// inside its work tree
+fn open_root(root: &Path) -> Option<(Repository, PathBuf)> {
+    let absolute = std::fs::canonicalize(root).ok()?;
+    let repo = Repository::discover(&absolute).ok()?;
+    let workdir = std::fs::canonicalize(repo.workdir()?).ok()?;
+    let relative = absolute.strip_prefix(&workdir).ok()?.to_path_buf();
+    Some((repo, relative))
+}
+
+/// The commit summary shown in history and blame previews
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub short_id: String,
+    pub author: String,
+    /// Seconds since the epoch
+    pub time: i64,
+    pub subject: String,
+}
+
+impl CommitInfo {
+    fn from_commit(commit: &Commit<'_>) -> Self {
+        CommitInfo {
+            short_id: commit.id().to_string()[..SHORT_ID_LEN].to_string(),
+            author: commit.author().name().unwrap_or("unknown").to_string(),
+            time: commit.time().seconds(),
+            subject: commit.summary().unwrap_or("").to_string(),
+        }
+    }
+}
+
+/// Length of abbreviated commit hashes, as `git log --oneline` prints them
+const SHORT_ID_LEN: usize = 7;
+
+/// How far back through history to look for commits touching a file
+const MAX_HISTORY_WALK: usize = 10_000;
+
+/// The most recent commits reachable from HEAD that changed `path`, newest first
+pub fn file_history(path: &Path, limit: usize) -> Option<Vec<CommitInfo>> {
+    let (repo, relative) = open_repo(path)?;
+    let mut walk = repo.revwalk().ok()?;
+    walk.push_head().ok()?;
+
+    let entry_id = |commit: &Commit<'_>| {
+        commit
+            .tree()
+            .ok()
+            .and_then(|tree| tree.get_path(&relative).ok())
+            .map(|entry| entry.id())
+    };
+
+    let mut commits = Vec::new();
+    for oid in walk.take(MAX_HISTORY_WALK).filter_map(|oid| oid.ok()) {
+        let Ok(commit) = repo.find_commit(oid) else {
+            continue;
+        };
+        let current = entry_id(&commit);
+        if current.is_none() {
+            continue;
+        }
+        // Changed if it differs from every parent, so merges that just carry a change
+        // over from one side aren't listed twice
+        let changed = commit.parent_count() == 0
+            || commit.parents().all(|parent| entry_id(&parent) != current);
+        if changed {
+            commits.push(CommitInfo::from_commit(&commit));
+            if commits.len() >= limit {
+                break;
+            }
+        }
+    }
+    Some(commits)
+}
+
+/// The commit that last touched each of the first `lines` lines of the file as it is on
+/// disk. Lines with uncommitted changes come back as `None`.
+pub fn blame_lines(path: &Path, lines: usize) -> Option<Vec<Option<CommitInfo>>> {
+    let (repo, relative) = open_repo(path)?;
+    let committed = repo.blame_file(&relative, None).ok()?;
+    // Blamed as raw bytes, since that's what the blobs hold whatever the encoding
+    let contents = std::fs::read(path).ok()?;
+    let blame = committed.blame_buffer(&contents).ok()?;
+
+    let mut commits: HashMap<Oid, Option<CommitInfo>> = HashMap::new();
+    let lines = (1..=lines)
+        .map(|line| {
+            let oid = blame.get_line(line)?.final_commit_id();
+            if oid.is_zero() {
+                return None;
+            }
+            commits
+                .entry(oid)
+                .or_insert_with(|| {
+                    repo.find_commit(oid)
+                        .ok()
+                        .map(|commit| CommitInfo::from_commit(&commit))
+                })
+                .clone()
+        })
+        .collect();
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir_with;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    /// A repository in a fresh temporary directory holding `files`, uncommitted
+    fn repo_with(files: &[(&str, &[u8])]) -> (TempDir, Repository) {
+        let dir = temp_dir_with(files);
+        let repo = Repository::init(dir.path()).unwrap();
+        (dir, repo)
+    }
+
+    /// Stages everything in the work tree and commits it on HEAD
+    fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Tester", "tester@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&Commit<'_>> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn write(dir: &TempDir, name: &str, contents: &str) {
+        std::fs::write(dir.path().join(name), contents).unwrap();
+    }
+
+    #[test]
+    fn diffs_uncommitted_changes_against_head() {
+        let (dir, repo) = repo_with(&[("a.txt", b"one\ntwo\n")]);
+        commit_all(&repo, "add a");
+        let path = dir.path().join("a.txt");
+        assert!(diff_against_head(&path).is_none());
+
+        write(&dir, "a.txt", "one\n2\n");
+        let diff = diff_against_head(&path).unwrap();
+        let origins: String = diff.iter().map(|line| line.origin).collect();
+        assert_eq!(origins, "FFFFH -+");
+        assert_eq!(diff[0].content, "diff --git a/a.txt b/a.txt");
+        assert_eq!(diff[6].content, "two");
+        assert_eq!(diff[7].content, "2");
+    }
+
+    #[test]
+    fn staged_files_diff_against_an_empty_tree_before_the_first_commit() {
+        let (dir, repo) = repo_with(&[("new.txt", b"hello\n")]);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("new.txt")).unwrap();
+        index.write().unwrap();
+
+        let diff = diff_against_head(&dir.path().join("new.txt")).unwrap();
+        let added: Vec<&str> = diff
+            .iter()
+            .filter(|line| line.origin == '+')
+            .map(|line| line.content.as_str())
+            .collect();
+        assert_eq!(added, ["hello"]);
+    }
+
+    #[test]
+    fn files_outside_a_repository_have_no_diff() {
+        let dir = temp_dir_with(&[("a.txt", b"x")]);
+        assert!(diff_against_head(&dir.path().join("a.txt")).is_none());
+    }
+
+    /// A committed `a.txt` since modified, a staged `staged.txt`, an untracked
+    /// `sub/new.txt` and an ignored `build/`
+    fn repo_in_every_state() -> (TempDir, Repository) {
+        let (dir, repo) = repo_with(&[
+            ("a.txt", b"a"),
+            ("clean.txt", b"c"),
+            (".gitignore", b"build/\n"),
+        ]);
+        commit_all(&repo, "initial");
+        write(&dir, "a.txt", "changed");
+        write(&dir, "staged.txt", "s");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("staged.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        write(&dir, "sub/new.txt", "n");
+        std::fs::create_dir_all(dir.path().join("build")).unwrap();
+        write(&dir, "build/out", "o");
+        (dir, repo)
+    }
+
+    #[test]
+    fn marks_files_like_git_status_short() {
+        let (dir, _repo) = repo_in_every_state();
+        let root = dir.path();
+        let markers = status_markers(root).unwrap();
+
+        assert_eq!(markers.get(&root.join("a.txt")), Some(&'M'));
+        assert_eq!(markers.get(&root.join("staged.txt")), Some(&'A'));
+        assert_eq!(markers.get(&root.join("sub/new.txt")), Some(&'?'));
+        assert_eq!(markers.get(&root.join("build")), Some(&'!'));
+        assert_eq!(markers.get(&root.join("clean.txt")), None);
+
+        // Paths are relative to the root even when it is below the top of the work tree
+        let sub = status_markers(&root.join("sub")).unwrap();
+        assert_eq!(sub.get(&root.join("sub").join("new.txt")), Some(&'?'));
+    }
+
+    #[test]
+    fn collects_files_by_state() {
+        let (dir, _repo) = repo_in_every_state();
+        let root = dir.path();
+        let collect = |filter: GitFilter| {
+            let files = collect_git_files(root, &filter).unwrap();
+            files
+                .iter()
+                .map(|path| {
+                    path.strip_prefix(root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let modified = GitFilter {
+            modified: true,
+            ..Default::default()
+        };
+        assert_eq!(collect(modified), ["a.txt", "staged.txt"]);
+        let untracked = GitFilter {
+            untracked: true,
+            ..Default::default()
+        };
+        assert_eq!(collect(untracked), ["sub/new.txt"]);
+        let tracked = GitFilter {
+            tracked: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            collect(tracked),
+            [".gitignore", "a.txt", "clean.txt", "staged.txt"]
+        );
+    }
+
+    #[test]
+    fn collects_files_changed_since_a_revision() {
+        let (dir, repo) = repo_with(&[("a.txt", b"a"), ("b.txt", b"b")]);
+        commit_all(&repo, "initial");
+        repo.branch(
+            "base",
+            &repo.head().unwrap().peel_to_commit().unwrap(),
+            false,
+        )
+        .unwrap();
+        write(&dir, "b.txt", "changed");
+        commit_all(&repo, "change b");
+        std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+
+        let filter = GitFilter {
+            changed_since: Some("base".to_string()),
+            ..Default::default()
+        };
+        // The deleted a.txt is left out
+        let files = collect_git_files(dir.path(), &filter).unwrap();
+        assert_eq!(files, [dir.path().join("b.txt")]);
+    }
+
+    #[test]
+    fn filtering_outside_a_repository_is_an_error() {
+        let dir = temp_dir_with(&[]);
+        let filter = GitFilter {
+            tracked: true,
+            ..Default::default()
+        };
+        assert!(collect_git_files(dir.path(), &filter).is_err());
+        assert!(status_markers(dir.path()).is_none());
+    }
+
+    #[test]
+    fn history_lists_commits_that_touched_the_file_newest_first() {
+        let (dir, repo) = repo_with(&[("a.txt", b"1"), ("b.txt", b"1")]);
+        commit_all(&repo, "add both");
+        write(&dir, "b.txt", "2");
+        commit_all(&repo, "change b");
+        write(&dir, "a.txt", "2");
+        commit_all(&repo, "change a");
+        write(&dir, "a.txt", "3");
+        commit_all(&repo, "change a again");
+
+        let path = dir.path().join("a.txt");
+        let subjects = |limit| {
+            file_history(&path, limit)
+                .unwrap()
+                .into_iter()
+                .map(|commit| commit.subject)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(subjects(10), ["change a again", "change a", "add both"]);
+        assert_eq!(subjects(1), ["change a again"]);
+
+        let newest = &file_history(&path, 1).unwrap()[0];
+        assert_eq!(newest.author, "Tester");
+        assert_eq!(newest.short_id.len(), SHORT_ID_LEN);
+    }
+
+    #[test]
+    fn blame_leaves_uncommitted_lines_out() {
+        // Latin-1 bytes, which aren't valid UTF-8, are blamed as they are on disk
+        let (dir, repo) = repo_with(&[("a.txt", b"caf\xe9\nsecond\n")]);
+        commit_all(&repo, "first");
+        std::fs::write(dir.path().join("a.txt"), b"caf\xe9\nedited\nadded\n").unwrap();
+
+        let blame = blame_lines(&dir.path().join("a.txt"), 4).unwrap();
+        let subjects: Vec<Option<&str>> = blame
+            .iter()
+            .map(|commit| commit.as_ref().map(|c| c.subject.as_str()))
+            .collect();
+        assert_eq!(subjects, [Some("first"), None, None, None]);
+    }
+
+    #[test]
+    fn untracked_files_have_no_blame() {
+        let (dir, repo) = repo_with(&[("a.txt", b"a")]);
+        commit_all(&repo, "first");
+        write(&dir, "new.txt", "n");
+        assert!(blame_lines(&dir.path().join("new.txt"), 1).is_none());
+    }
+}
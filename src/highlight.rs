@@ -1,68 +1,296 @@
+use ignore::WalkBuilder;
+use serde::{Serialize, de::DeserializeOwned};
 use syntect::{
+    dumps::{dump_to_file, from_dump_file},
     easy::HighlightLines,
-    highlighting::{Style as SyntectStyle, ThemeSet},
+    highlighting::{FontStyle, Style as SyntectStyle, ThemeSet},
     parsing::SyntaxSet,
 };
 
 use ratatui::{
-    style::Style,
+    style::{Modifier, Style},
     text::{Line, Span, Text},
 };
 
-use std::path::Path;
+use std::{path::Path, time::SystemTime};
+
+use crate::color::ColorDepth;
+use crate::config::{cache_dir, config_dir};
+use crate::language::detect_syntax;
+use crate::preview::{MAX_PREVIEW_LINES, PreviewOptions, expand_tabs};
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
 
 lazy_static::lazy_static! {
-    pub static ref SS: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    pub static ref TS: ThemeSet = ThemeSet::load_defaults();
+    pub static ref SS: SyntaxSet = load_syntaxes();
+    pub static ref TS: ThemeSet = load_themes();
+}
+
+/// Bundled syntaxes plus any `.sublime-syntax` files in the config's `syntaxes/` folder
+fn load_syntaxes() -> SyntaxSet {
+    load_with_cache(
+        "syntaxes",
+        "syntaxes.bin",
+        SyntaxSet::load_defaults_newlines,
+        |dir| {
+            let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+            builder.add_from_folder(dir, true).ok()?;
+            Some(builder.build())
+        },
+    )
+}
+
+/// Bundled themes plus any `.tmTheme` files in the config's `themes/` folder
+fn load_themes() -> ThemeSet {
+    load_with_cache("themes", "themes.bin", ThemeSet::load_defaults, |dir| {
+        let mut themes = ThemeSet::load_defaults();
+        themes.add_from_folder(dir).ok()?;
+        Some(themes)
+    })
+}
+
+/// Parsing user syntaxes is slow, so the merged set is dumped to the cache directory and
+/// reused until something in the source folder is newer than the dump.
+fn load_with_cache<T: Serialize + DeserializeOwned>(
+    folder: &str,
+    dump_name: &str,
+    defaults: fn() -> T,
+    build: impl FnOnce(&Path) -> Option<T>,
+) -> T {
+    let Some(source) = config_dir().map(|dir| dir.join(folder)) else {
+        return defaults();
+    };
+    let Some(newest_source) = newest_mtime(&source) else {
+        return defaults();
+    };
+    let dump = cache_dir().map(|dir| dir.join(dump_name));
+
+    if let Some(dump) = &dump {
+        let dump_is_fresh = std::fs::metadata(dump)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified >= newest_source);
+        if dump_is_fresh && let Ok(cached) = from_dump_file(dump) {
+            return cached;
+        }
+    }
+
+    let Some(loaded) = build(&source) else {
+        return defaults();
+    };
+    if let Some(dump) = &dump
+        && let Some(parent) = dump.parent()
+        && std::fs::create_dir_all(parent).is_ok()
+    {
+        let _ = dump_to_file(&loaded, dump);
+    }
+    loaded
 }
 
-pub fn highlight_contents<'a>(
+/// Latest modification time of `dir` or anything under it, `None` if it is missing or empty.
+/// The folder itself is included so that deleting a file also invalidates the dump.
+fn newest_mtime(dir: &Path) -> Option<SystemTime> {
+    let mtimes: Vec<SystemTime> = WalkBuilder::new(dir)
+        .standard_filters(false)
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .collect();
+
+    if mtimes.len() < 2 {
+        return None;
+    }
+    mtimes.into_iter().max()
+}
+
+/// Names of every loaded theme, in the order they are cycled through
+pub fn theme_names() -> Vec<&'static str> {
+    TS.themes.keys().map(String::as_str).collect()
+}
+
+/// The theme after `current`, wrapping around at the end
+pub fn next_theme(current: &str) -> String {
+    let names = theme_names();
+    let next = names
+        .iter()
+        .position(|name| *name == current)
+        .map(|i| (i + 1) % names.len())
+        .unwrap_or(0);
+    names[next].to_string()
+}
+
+pub fn highlight_contents<'a>(file_path: &Path, content: &str, opts: &PreviewOptions) -> Text<'a> {
+    highlight_lines(file_path, content, opts, MAX_PREVIEW_LINES)
+}
+
+/// Highlights the first `max_lines` lines of `content`
+pub fn highlight_lines<'a>(
     file_path: &Path,
     content: &str,
-    prev_height: u16,
-    prev_width: u16,
+    opts: &PreviewOptions,
+    max_lines: usize,
 ) -> Text<'a> {
-    let syntax = SS
-        .find_syntax_for_file(file_path)
-        .ok()
-        .flatten()
-        .unwrap_or_else(|| SS.find_syntax_plain_text());
+    let syntax = detect_syntax(file_path, content, &opts.syntax_map);
 
-    let mut h = HighlightLines::new(syntax, &TS.themes["base16-ocean.dark"]);
+    let theme = TS
+        .themes
+        .get(&opts.theme)
+        .unwrap_or_else(|| &TS.themes[DEFAULT_THEME]);
+    let mut h = HighlightLines::new(syntax, theme);
 
     let mut lines_to_render = Vec::new();
-    let max_display_lines = 50;
 
-    for line_str in content.lines().take(max_display_lines) {
-        let ranges: Vec<(SyntectStyle, &str)> = h.highlight_line(line_str, &SS).unwrap_or_default();
+    for line_str in content.lines().take(max_lines) {
+        let line_str = expand_tabs(line_str, opts.tab_width);
+        let ranges: Vec<(SyntectStyle, &str)> =
+            h.highlight_line(&line_str, &SS).unwrap_or_default();
 
-        let mut spans: Vec<Span<'a>> = ranges
+        let spans: Vec<Span<'a>> = ranges
             .into_iter()
             .map(|(style, text)| {
                 Span::styled(
                     text.to_string(),
-                    Style::default().fg(convert_syntect_color(style.foreground)),
+                    convert_syntect_style(style, opts.color_depth),
                 )
             })
             .collect();
-        spans.push(Span::styled(
-            " ".repeat(prev_width as usize),
-            Style::default(),
-        ));
 
         lines_to_render.push(Line::from(spans));
     }
 
-    while (lines_to_render.len() as u16) < prev_height {
-        lines_to_render.push(Line::from(Span::styled(
-            " ".repeat(prev_height as usize),
-            Style::default(),
-        )));
+    // The theme's own background is filled in behind the whole pane, so light themes
+    // don't end up as dark text on the terminal's default background
+    let background = theme
+        .settings
+        .background
+        .filter(|bg| bg.a > 0)
+        .map(|bg| opts.color_depth.rgb(bg.r, bg.g, bg.b));
+    let mut text = Text::from(lines_to_render);
+    if let Some(bg) = background {
+        text = text.style(Style::default().bg(bg));
+    }
+    text
+}
+
+/// Carries over colors and font styles from syntect, downsampled to what the terminal supports
+fn convert_syntect_style(style: SyntectStyle, depth: ColorDepth) -> Style {
+    let mut converted = Style::default().fg(convert_syntect_color(style.foreground, depth));
+    if style.background.a > 0 {
+        converted = converted.bg(convert_syntect_color(style.background, depth));
+    }
+
+    if style.font_style.contains(FontStyle::BOLD) {
+        converted = converted.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        converted = converted.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        converted = converted.add_modifier(Modifier::UNDERLINED);
     }
+    converted
+}
 
-    Text::from(lines_to_render)
+fn convert_syntect_color(
+    color: syntect::highlighting::Color,
+    depth: ColorDepth,
+) -> ratatui::style::Color {
+    depth.rgb(color.r, color.g, color.b)
 }
 
-fn convert_syntect_color(color: syntect::highlighting::Color) -> ratatui::style::Color {
-    ratatui::style::Color::Rgb(color.r, color.g, color.b)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir_with;
+
+    #[test]
+    fn themes_cycle_and_wrap_around() {
+        let names = theme_names();
+        assert!(names.contains(&DEFAULT_THEME));
+
+        let mut seen = vec![DEFAULT_THEME.to_string()];
+        loop {
+            let next = next_theme(seen.last().unwrap());
+            if next == DEFAULT_THEME {
+                break;
+            }
+            seen.push(next);
+        }
+        assert_eq!(seen.len(), names.len());
+    }
+
+    #[test]
+    fn unknown_theme_goes_to_the_first() {
+        assert_eq!(next_theme("no such theme"), theme_names()[0]);
+    }
+
+    #[test]
+    fn empty_or_missing_folders_have_no_mtime() {
+        let dir = temp_dir_with(&[]);
+        assert_eq!(newest_mtime(dir.path()), None);
+        assert_eq!(newest_mtime(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn newest_mtime_covers_nested_files() {
+        let dir = temp_dir_with(&[("a.tmTheme", b""), ("nested/b.tmTheme", b"")]);
+        let later = SystemTime::now() + std::time::Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("nested/b.tmTheme"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(newest_mtime(dir.path()), Some(later));
+    }
+
+    #[test]
+    fn font_styles_and_colors_are_carried_over() {
+        let style = SyntectStyle {
+            foreground: syntect::highlighting::Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            background: syntect::highlighting::Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+            font_style: FontStyle::BOLD | FontStyle::ITALIC | FontStyle::UNDERLINE,
+        };
+
+        let converted = convert_syntect_style(style, ColorDepth::TrueColor);
+        assert_eq!(converted.fg, Some(ratatui::style::Color::Rgb(255, 0, 0)));
+        // A transparent background leaves the theme's one showing through
+        assert_eq!(converted.bg, None);
+        assert!(
+            converted
+                .add_modifier
+                .contains(Modifier::BOLD | Modifier::ITALIC | Modifier::UNDERLINED)
+        );
+
+        let downsampled = convert_syntect_style(style, ColorDepth::Ansi16);
+        assert_eq!(downsampled.fg, Some(ratatui::style::Color::LightRed));
+    }
+
+    #[test]
+    fn theme_background_fills_the_text() {
+        let mut opts = PreviewOptions::for_tests();
+        opts.color_depth = ColorDepth::Ansi256;
+        let text = highlight_contents(Path::new("main.rs"), "fn main() {}\n", &opts);
+
+        let bg = TS.themes[DEFAULT_THEME].settings.background.unwrap();
+        assert_eq!(
+            text.style.bg,
+            Some(ColorDepth::Ansi256.rgb(bg.r, bg.g, bg.b))
+        );
+        let colors = text.lines[0].spans.iter().filter_map(|span| span.style.fg);
+        assert!(
+            colors
+                .into_iter()
+                .all(|c| matches!(c, ratatui::style::Color::Indexed(_)))
+        );
+    }
 }
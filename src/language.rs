@@ -0,0 +1,215 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use indexmap::IndexMap;
+use std::path::Path;
+use syntect::parsing::SyntaxReference;
+
+use crate::Result;
+use crate::highlight::SS;
+
+/// How many lines at each end of a file are searched for vim/emacs modelines
+const MODELINE_LINES: usize = 5;
+
+/// User supplied glob patterns mapped to syntax names, from the config's `[syntax_map]`
+#[derive(Debug, Default)]
+pub struct SyntaxMap {
+    globs: GlobSet,
+    syntaxes: Vec<&'static SyntaxReference>,
+}
+
+impl SyntaxMap {
+    /// Patterns are kept in the order they are written in, which decides between
+    /// several that match
+    pub fn new(map: &IndexMap<String, String>) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut syntaxes = Vec::new();
+        for (pattern, name) in map {
+            let syntax = find_syntax_by_name(name)
+                .ok_or_else(|| format!("syntax_map: no syntax called '{name}'"))?;
+            builder.add(Glob::new(pattern).map_err(|e| format!("syntax_map: {e}"))?);
+            syntaxes.push(syntax);
+        }
+
+        Ok(SyntaxMap {
+            globs: builder.build()?,
+            syntaxes,
+        })
+    }
+
+    /// Patterns are tried against both the whole path and the bare file name, so
+    /// `Dockerfile.*` works without a leading `**/`
+    fn lookup(&self, path: &Path) -> Option<&'static SyntaxReference> {
+        let mut matches = self.globs.matches(path);
+        if let Some(name) = path.file_name() {
+            matches.extend(self.globs.matches(name));
+        }
+        // The pattern written first wins, whatever order the matches come back in
+        matches.iter().min().map(|i| self.syntaxes[*i])
+    }
+}
+
+/// Works out which syntax to highlight a file with. In order of priority: the user's
+/// syntax map, a vim or emacs modeline, the file name or extension, the first line
+/// (shebangs, `<?xml`, ...), and finally the name with its last suffix dropped, so that
+/// `Cargo.toml.orig` or `nginx.conf.j2` are treated like the file they are a variant of.
+pub fn detect_syntax(path: &Path, content: &str, map: &SyntaxMap) -> &'static SyntaxReference {
+    map.lookup(path)
+        .or_else(|| modeline_syntax(content))
+        .or_else(|| syntax_for_name(path))
+        .or_else(|| SS.find_syntax_by_first_line(content.lines().next()?))
+        .or_else(|| syntax_for_name(Path::new(path.file_stem()?)))
+        .unwrap_or_else(|| SS.find_syntax_plain_text())
+}
+
+/// Looks a syntax up by its display name ("Python") or a token such as "py" or "sh"
+pub fn find_syntax_by_name(name: &str) -> Option<&'static SyntaxReference> {
+    SS.find_syntax_by_name(name)
+        .or_else(|| SS.find_syntax_by_token(name))
+}
+
+/// Matches whole file names first (`Makefile`) and then the extension
+fn syntax_for_name(path: &Path) -> Option<&'static SyntaxReference> {
+    let file_name = path.file_name()?.to_str()?;
+    SS.find_syntax_by_extension(file_name).or_else(|| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| SS.find_syntax_by_extension(ext))
+    })
+}
+
+fn modeline_syntax(content: &str) -> Option<&'static SyntaxReference> {
+    let lines: Vec<&str> = content.lines().collect();
+    let tail_start = lines
+        .len()
+        .saturating_sub(MODELINE_LINES)
+        .max(MODELINE_LINES);
+    let candidates = lines
+        .iter()
+        .take(MODELINE_LINES)
+        .chain(lines.iter().skip(tail_start));
+
+    for line in candidates {
+        if let Some(name) = vim_filetype(line).or_else(|| emacs_mode(line)) {
+            return find_syntax_by_name(name);
+        }
+    }
+    None
+}
+
+/// `vim: set ft=python:`, `vi: ft=sh` or `ex: syntax=ruby`
+fn vim_filetype(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let idx = line.find(marker)?;
+        let at_word_start = line[..idx]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        at_word_start.then_some(idx + marker.len())
+    })?;
+
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key, "ft" | "filetype" | "syntax" | "syn").then_some(value)
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// `-*- mode: python -*-` or the short form `-*- python -*-`
+fn emacs_mode(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let len = line[start..].find("-*-")?;
+    let inner = line[start..start + len].trim();
+
+    if !inner.contains(':') {
+        return Some(inner).filter(|mode| !mode.is_empty());
+    }
+    inner.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then_some(value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> SyntaxMap {
+        let map = pairs
+            .iter()
+            .map(|(pattern, name)| (pattern.to_string(), name.to_string()))
+            .collect();
+        SyntaxMap::new(&map).unwrap()
+    }
+
+    fn detected(path: &str, content: &str, map: &SyntaxMap) -> String {
+        detect_syntax(Path::new(path), content, map).name.clone()
+    }
+
+    #[test]
+    fn vim_modelines() {
+        assert_eq!(vim_filetype("# vim: set ft=python:"), Some("python"));
+        assert_eq!(vim_filetype("// vi: ts=4 filetype=sh"), Some("sh"));
+        assert_eq!(vim_filetype("ex: syntax=ruby"), Some("ruby"));
+        assert_eq!(vim_filetype("# vim: set ts=4:"), None);
+        // Only at the start of a word, so an address like "svim:" isn't one
+        assert_eq!(vim_filetype("svim: ft=python"), None);
+    }
+
+    #[test]
+    fn emacs_modelines() {
+        assert_eq!(emacs_mode("# -*- mode: python -*-"), Some("python"));
+        assert_eq!(
+            emacs_mode(";; -*- coding: utf-8; Mode: lisp -*-"),
+            Some("lisp")
+        );
+        assert_eq!(emacs_mode("# -*- ruby -*-"), Some("ruby"));
+        assert_eq!(emacs_mode("# -*- coding: utf-8 -*-"), None);
+        assert_eq!(emacs_mode("-*- unterminated"), None);
+    }
+
+    #[test]
+    fn modelines_are_looked_for_at_either_end() {
+        let mut content = "# vim: ft=python\n".to_string();
+        assert_eq!(detected("script", &content, &map(&[])), "Python");
+        content = format!("{}# vim: ft=ruby\n", "x\n".repeat(20));
+        assert_eq!(detected("script", &content, &map(&[])), "Ruby");
+        content = format!("{}# vim: ft=ruby\n{}", "x\n".repeat(10), "x\n".repeat(10));
+        assert_eq!(detected("script", &content, &map(&[])), "Plain Text");
+    }
+
+    #[test]
+    fn detection_order() {
+        let none = map(&[]);
+        let shebang = "#!/usr/bin/env python\n";
+        // The name beats the first line, which beats the stem
+        assert_eq!(
+            detected("run.sh", shebang, &none),
+            "Bourne Again Shell (bash)"
+        );
+        assert_eq!(detected("run", shebang, &none), "Python");
+        assert_eq!(detected("main.rs.orig", "", &none), "Rust");
+        assert_eq!(detected("run.bak", "", &none), "Plain Text");
+        // A modeline beats the name
+        assert_eq!(detected("run.sh", "# vim: ft=python\n", &none), "Python");
+        // The syntax map beats everything
+        let map = map(&[("run.*", "Ruby")]);
+        assert_eq!(detected("run.sh", "# vim: ft=python\n", &map), "Ruby");
+    }
+
+    #[test]
+    fn the_first_matching_pattern_wins() {
+        let first = map(&[("*.conf", "YAML"), ("nginx.*", "Python")]);
+        assert_eq!(detected("etc/nginx.conf", "", &first), "YAML");
+        let second = map(&[("nginx.*", "Python"), ("*.conf", "YAML")]);
+        assert_eq!(detected("etc/nginx.conf", "", &second), "Python");
+    }
+
+    #[test]
+    fn unknown_syntax_names_are_rejected() {
+        let map = [("*.x".to_string(), "No Such Language".to_string())]
+            .into_iter()
+            .collect();
+        assert!(SyntaxMap::new(&map).is_err());
+    }
+}
@@ -1,27 +1,298 @@
-use clap::Parser;
-use filesystem::collect_files;
+use clap::{Parser, Subcommand};
+use color::ColorDepth;
+use config::Config;
+use filesystem::FileSource;
+use git::GitFilter;
+use highlight::{DEFAULT_THEME, TS, theme_names};
+use language::SyntaxMap;
 use nucleo::Matcher;
-use ui::run_app;
+use preview::PreviewOptions;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+use ui::{Outcome, restore_terminal, run_app};
 
+mod color;
+mod config;
 mod filesystem;
+mod git;
 mod highlight;
+mod language;
+mod preview;
+mod sanitize;
+#[cfg(test)]
+mod test_util;
 mod ui;
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Parser, Debug)]
 #[command(name = "findr", version, about = "Fuzzy finder written in Rust")]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(default_value = ".")]
     pub path: String,
+
+    /// Syntax highlighting theme used in the preview (see `findr themes`)
+    #[arg(long)]
+    pub theme: Option<String>,
+
+    /// Color depth to render with instead of detecting it from COLORTERM/TERM
+    #[arg(long, value_enum)]
+    pub color: Option<ColorDepth>,
+
+    /// Command to preview selections with instead of the built-in previews.
+    /// `{}` is replaced by the path, `{q}` by the query and `{line}` by the line in focus
+    #[arg(long, value_name = "COMMAND")]
+    pub preview: Option<String>,
+
+    /// Only list files with staged or unstaged changes, including newly added ones
+    #[arg(long)]
+    pub git_modified: bool,
+
+    /// Only list untracked files
+    #[arg(long)]
+    pub git_untracked: bool,
+
+    /// Only list files tracked by git
+    #[arg(long)]
+    pub git_tracked: bool,
+
+    /// Only list files changed since the merge base with this branch or revision
+    #[arg(long, value_name = "REV")]
+    pub git_changed_since: Option<String>,
+
+    /// Also list the files inside zip and tar archives, as `archive.tar.gz!/member`.
+    /// Print mode prints these paths as they are, they don't exist on disk
+    #[arg(long)]
+    pub search_archives: bool,
+
+    /// Print the selected paths to stdout on Enter instead of opening them, drawing the
+    /// UI on the terminal. The default when stdout isn't a terminal
+    #[arg(long)]
+    pub print: bool,
+
+    /// Separate printed paths with NUL bytes instead of newlines (implies --print)
+    #[arg(long)]
+    pub print0: bool,
+
+    /// Print the query before the selected paths (implies --print)
+    #[arg(long)]
+    pub print_query: bool,
+
+    /// Print absolute paths rather than paths as they were found
+    #[arg(long)]
+    pub absolute: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the available preview themes, including ones from the config directory
+    Themes,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    let config = Config::load()?;
+
+    if let Some(Command::Themes) = args.command {
+        for name in theme_names() {
+            println!("{name}");
+        }
+        return Ok(());
+    }
 
-    let files = collect_files(&args.path, true)?;
+    let theme = args
+        .theme
+        .or(config.theme)
+        .unwrap_or_else(|| DEFAULT_THEME.to_string());
+    if !TS.themes.contains_key(&theme) {
+        return Err(format!("unknown theme '{theme}', run `findr themes` to list them").into());
+    }
+    let color_depth = args
+        .color
+        .or(config.color)
+        .unwrap_or_else(ColorDepth::detect);
+    let preview_opts = PreviewOptions {
+        theme,
+        color_depth,
+        line_numbers: config.line_numbers.unwrap_or(true),
+        wrap: config.wrap.unwrap_or(false),
+        tab_width: config.tab_width.unwrap_or(4),
+        interpret_ansi: config.interpret_ansi.unwrap_or(true),
+        syntax_map: Arc::new(SyntaxMap::new(&config.syntax_map)?),
+        show_diff: config.git_diff.unwrap_or(true),
+        show_metadata: config.show_metadata.unwrap_or(false),
+        git_view: None,
+        structured: config.structured_previews.unwrap_or(true),
+        collapse_depth: config.collapse_depth,
+        compare_with: None,
+        compare_pair: None,
+        side_by_side: false,
+        preview_command: args.preview,
+        preview_commands: Arc::new(
+            config
+                .preview_commands
+                .into_iter()
+                .map(|(ext, command)| (ext.to_lowercase(), command))
+                .collect(),
+        ),
+        preview_timeout: Duration::from_millis(config.preview_timeout_ms.unwrap_or(3000)),
+    };
+
+    let source = FileSource {
+        git: GitFilter {
+            modified: args.git_modified,
+            untracked: args.git_untracked,
+            tracked: args.git_tracked,
+            changed_since: args.git_changed_since,
+        },
+        search_archives: args.search_archives,
+    };
+    let files = source.collect(&args.path)?;
     let mut matcher = Matcher::default();
-    let _ = run_app(&files, &mut matcher);
+    let cache_budget = config.preview_cache_mb.unwrap_or(64) * 1024 * 1024;
+    let print_mode = args.print || args.print0 || args.print_query || !io::stdout().is_terminal();
+    let outcome = match run_app(
+        PathBuf::from(&args.path),
+        files,
+        &source,
+        &mut matcher,
+        preview_opts,
+        cache_budget,
+        print_mode,
+    ) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            restore_terminal(print_mode);
+            if !print_mode {
+                return Err(e);
+            }
+            // Exit codes 0, 1 and 130 mean something to scripts, so errors get their own
+            eprintln!("Error: {e}");
+            process::exit(2);
+        }
+    };
+    if !print_mode {
+        return Ok(());
+    }
+
+    let separator = if args.print0 { b'\0' } else { b'\n' };
+    let code = print_outcome(
+        &mut io::stdout().lock(),
+        outcome,
+        separator,
+        args.print_query,
+        args.absolute,
+    )?;
+    process::exit(code);
+}
+
+/// Writes what print mode selected to `out` and returns the exit code: 0 when paths were
+/// selected, 1 when nothing matched and 130 when aborted, like other fuzzy finders
+fn print_outcome(
+    out: &mut impl Write,
+    outcome: Outcome,
+    separator: u8,
+    print_query: bool,
+    absolute: bool,
+) -> Result<i32> {
+    let mut print = |bytes: &[u8]| -> io::Result<()> {
+        out.write_all(bytes)?;
+        out.write_all(&[separator])
+    };
+    let code = match outcome {
+        Outcome::Selected { paths, query } => {
+            if print_query {
+                print(query.as_bytes())?;
+            }
+            for path in paths {
+                let path = if absolute {
+                    std::path::absolute(&path)?
+                } else {
+                    path
+                };
+                print(path.as_os_str().as_encoded_bytes())?;
+            }
+            0
+        }
+        Outcome::NoMatch { query } => {
+            if print_query {
+                print(query.as_bytes())?;
+            }
+            1
+        }
+        Outcome::Aborted => 130,
+    };
+    out.flush()?;
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printed(outcome: Outcome, separator: u8, print_query: bool) -> (i32, String) {
+        let mut out = Vec::new();
+        let code = print_outcome(&mut out, outcome, separator, print_query, false).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    fn selected() -> Outcome {
+        Outcome::Selected {
+            paths: vec![PathBuf::from("src/a.rs"), PathBuf::from("b c.txt")],
+            query: "ab".to_string(),
+        }
+    }
+
+    #[test]
+    fn selected_paths_are_printed_one_per_line() {
+        assert_eq!(
+            printed(selected(), b'\n', false),
+            (0, "src/a.rs\nb c.txt\n".into())
+        );
+        assert_eq!(
+            printed(selected(), b'\n', true),
+            (0, "ab\nsrc/a.rs\nb c.txt\n".into())
+        );
+    }
+
+    #[test]
+    fn print0_separates_with_nul() {
+        assert_eq!(
+            printed(selected(), b'\0', false),
+            (0, "src/a.rs\0b c.txt\0".into())
+        );
+    }
+
+    #[test]
+    fn no_match_and_abort_have_their_own_codes() {
+        let no_match = || Outcome::NoMatch {
+            query: "zz".to_string(),
+        };
+        assert_eq!(printed(no_match(), b'\n', false), (1, String::new()));
+        assert_eq!(printed(no_match(), b'\n', true), (1, "zz\n".into()));
+        assert_eq!(printed(Outcome::Aborted, b'\n', true), (130, String::new()));
+    }
 
-    Ok(())
+    #[test]
+    fn absolute_paths_are_resolved_against_the_working_directory() {
+        let mut out = Vec::new();
+        print_outcome(&mut out, selected(), b'\n', false, true).unwrap();
+        let first = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            PathBuf::from(first),
+            std::env::current_dir().unwrap().join("src/a.rs")
+        );
+    }
 }
@@ -0,0 +1,40 @@
+use ansi_to_tui::IntoText;
+use ratatui::text::{Line, Span, Text};
+
+use crate::preview::{PreviewOptions, expand_tabs_in_line};
+
+/// Renders text containing ANSI SGR sequences (colored logs, captured terminal output)
+/// with those colors applied. Anything that isn't a color code is left for the sanitizer
+/// to make visible. Only the first `max_lines` lines are rendered.
+pub fn ansi_contents(content: &str, opts: &PreviewOptions, max_lines: usize) -> Text<'static> {
+    let head = content
+        .lines()
+        .take(max_lines)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let text = head
+        .as_bytes()
+        .into_text()
+        .unwrap_or_else(|_| Text::raw(head.clone()));
+
+    let lines: Vec<Line<'static>> = text
+        .lines
+        .into_iter()
+        .map(|line| {
+            let spans: Vec<Span<'static>> = line
+                .spans
+                .into_iter()
+                .map(|span| {
+                    let mut style = span.style;
+                    style.fg = style.fg.map(|c| opts.color_depth.adapt(c));
+                    style.bg = style.bg.map(|c| opts.color_depth.adapt(c));
+                    Span::styled(span.content, style)
+                })
+                .collect();
+            expand_tabs_in_line(Line::from(spans).style(line.style), opts.tab_width)
+        })
+        .collect();
+
+    Text::from(lines)
+}
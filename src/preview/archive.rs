@@ -0,0 +1,659 @@
+use chrono::{DateTime, Local};
+use flate2::read::MultiGzDecoder;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+use crate::filesystem::human_size;
+
+/// Stops archives with huge numbers of members from stalling the preview
+const MAX_ARCHIVE_ENTRIES: usize = 1000;
+
+/// Compressed tarballs have to be decompressed to be listed, this is as far as we go
+const MAX_ARCHIVE_SCAN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Members are read up to this size when extracted for editing
+const MAX_EXTRACT_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Separates the archive from the member inside it in a virtual path, as in
+/// `release.tar.gz!/bin/tool.conf`
+const MEMBER_SEPARATOR: &str = "!/";
+
+/// Extensions of zip based formats
+const ZIP_EXTENSIONS: [&str; 6] = ["zip", "jar", "war", "ear", "apk", "whl"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// A single compressed file, checked after [`ArchiveKind::from_path`] so that
+    /// compressed tarballs are listed rather than decompressed
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar(Option<Compression>),
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let ext = name.rsplit('.').next()?;
+        if ZIP_EXTENSIONS.contains(&ext) {
+            return Some(ArchiveKind::Zip);
+        }
+        let compression = match ext {
+            "tar" => None,
+            "tgz" => Some(Compression::Gzip),
+            "tzst" => Some(Compression::Zstd),
+            "txz" => Some(Compression::Xz),
+            _ if name.strip_suffix(ext)?.ends_with(".tar.") => Some(Compression::from_path(path)?),
+            _ => return None,
+        };
+        Some(ArchiveKind::Tar(compression))
+    }
+}
+
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<String>,
+    pub is_dir: bool,
+}
+
+pub struct ArchiveListing {
+    pub entries: Vec<ArchiveEntry>,
+    /// More entries exist than were listed
+    pub truncated: bool,
+}
+
+/// Lists the members of the archive at `path` without extracting them
+pub fn list_archive(path: &Path, kind: ArchiveKind) -> io::Result<ArchiveListing> {
+    let file = BufReader::new(File::open(path)?);
+    match kind {
+        ArchiveKind::Zip => list_zip(file),
+        ArchiveKind::Tar(compression) => list_tar(file, compression),
+    }
+}
+
+fn list_zip(file: BufReader<File>) -> io::Result<ArchiveListing> {
+    let mut archive = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len().min(MAX_ARCHIVE_ENTRIES) {
+        let member = archive.by_index_raw(i).map_err(io::Error::other)?;
+        entries.push(ArchiveEntry {
+            path: member.name().to_string(),
+            size: member.size(),
+            modified: member.last_modified().map(|time| {
+                format!(
+                    "{:04}-{:02}-{:02} {:02}:{:02}",
+                    time.year(),
+                    time.month(),
+                    time.day(),
+                    time.hour(),
+                    time.minute()
+                )
+            }),
+            is_dir: member.is_dir(),
+        });
+    }
+    Ok(ArchiveListing {
+        entries,
+        truncated: archive.len() > MAX_ARCHIVE_ENTRIES,
+    })
+}
+
+fn list_tar(file: BufReader<File>, compression: Option<Compression>) -> io::Result<ArchiveListing> {
+    let reader: Box<dyn Read> = match compression {
+        Some(compression) => decompress(file, compression, MAX_ARCHIVE_SCAN_BYTES)?,
+        None => Box::new(file.take(MAX_ARCHIVE_SCAN_BYTES)),
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    let mut truncated = false;
+
+    for entry in archive.entries()? {
+        // Running into the scan limit ends the stream mid-member, which shows up as an
+        // error reading the next header. An error before any header is a broken file.
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if entries.is_empty() => return Err(e),
+            Err(_) => {
+                truncated = true;
+                break;
+            }
+        };
+        if entries.len() == MAX_ARCHIVE_ENTRIES {
+            truncated = true;
+            break;
+        }
+        let header = entry.header();
+        entries.push(ArchiveEntry {
+            path: entry.path()?.to_string_lossy().into_owned(),
+            size: header.size().unwrap_or(0),
+            modified: header.mtime().ok().and_then(|mtime| {
+                DateTime::from_timestamp(mtime as i64, 0).map(|time| {
+                    time.with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+            }),
+            is_dir: header.entry_type().is_dir(),
+        });
+    }
+
+    Ok(ArchiveListing { entries, truncated })
+}
+
+/// A file inside an archive, which `--search-archives` lists under a virtual path
+#[derive(Debug, Clone)]
+pub struct ArchiveMember {
+    pub archive: PathBuf,
+    pub kind: ArchiveKind,
+    pub name: String,
+}
+
+impl ArchiveMember {
+    /// Splits a virtual path into the archive on disk and the member's name in it
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = path.to_str()?;
+        path.match_indices(MEMBER_SEPARATOR).find_map(|(i, _)| {
+            let archive = PathBuf::from(&path[..i]);
+            let kind = ArchiveKind::from_path(&archive)?;
+            archive.is_file().then(|| ArchiveMember {
+                archive,
+                kind,
+                name: path[i + MEMBER_SEPARATOR.len()..].to_string(),
+            })
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(format!(
+            "{}{MEMBER_SEPARATOR}{}",
+            self.archive.display(),
+            self.name
+        ))
+    }
+
+    /// The member's contents, at most `limit` bytes of them
+    pub fn read(&self, limit: u64) -> io::Result<Vec<u8>> {
+        let file = BufReader::new(File::open(&self.archive)?);
+        let mut bytes = Vec::new();
+        match self.kind {
+            ArchiveKind::Zip => {
+                let mut archive = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+                let member = archive.by_name(&self.name).map_err(io::Error::other)?;
+                member.take(limit).read_to_end(&mut bytes)?;
+            }
+            ArchiveKind::Tar(compression) => {
+                // The tar stream is decompressed only as far as the member and the first
+                // `limit` bytes of it, however much of the archive comes after
+                let reader: Box<dyn Read> = match compression {
+                    Some(compression) => decompress(file, compression, u64::MAX)?,
+                    None => Box::new(file),
+                };
+                let mut archive = tar::Archive::new(reader);
+                let member = archive
+                    .entries()?
+                    .filter_map(|entry| entry.ok())
+                    .find(|entry| {
+                        entry
+                            .path()
+                            .is_ok_and(|path| path.to_string_lossy() == self.name)
+                    })
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such member"))?;
+                member.take(limit).read_to_end(&mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Writes the member out to a temporary directory so it can be opened in an editor.
+    /// Only the normal components of the member's name are used, so a name such as
+    /// `../../.bashrc` can't place the file outside that directory.
+    pub fn extract(&self) -> io::Result<PathBuf> {
+        let archive_name = self.archive.file_name().unwrap_or_default();
+        let mut target = extract_dir().join(archive_name);
+        target.extend(Path::new(&self.name).components().filter_map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        }));
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, self.read(MAX_EXTRACT_BYTES)?)?;
+        Ok(target)
+    }
+}
+
+/// Where this process extracts archive members to
+fn extract_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("findr-{}", std::process::id()))
+}
+
+/// Removes the members extracted by [`ArchiveMember::extract`] when dropped, so they
+/// don't outlive the session however it ends
+pub struct ExtractedMembers;
+
+impl Drop for ExtractedMembers {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(extract_dir());
+    }
+}
+
+/// The virtual paths of the files inside the archive at `path`, empty if it can't be read
+pub fn member_paths(path: &Path, kind: ArchiveKind) -> Vec<PathBuf> {
+    let Ok(listing) = list_archive(path, kind) else {
+        return Vec::new();
+    };
+    listing
+        .entries
+        .into_iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| {
+            ArchiveMember {
+                archive: path.to_path_buf(),
+                kind,
+                name: entry.path,
+            }
+            .path()
+        })
+        .collect()
+}
+
+/// Decompresses the single compressed file at `path`, at most `limit` bytes of it
+pub fn read_compressed(path: &Path, compression: Compression, limit: u64) -> io::Result<Vec<u8>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut bytes = Vec::new();
+    decompress(file, compression, limit)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Wraps `reader` so that it yields at most `limit` decompressed bytes. Decompression
+/// happens as the result is read, so stopping early skips the rest of the input.
+fn decompress(
+    reader: impl Read + Send + 'static,
+    compression: Compression,
+    limit: u64,
+) -> io::Result<Box<dyn Read>> {
+    Ok(match compression {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader).take(limit)),
+        Compression::Zstd => Box::new(
+            ruzstd::decoding::StreamingDecoder::new(reader)
+                .map_err(io::Error::other)?
+                .take(limit),
+        ),
+        Compression::Xz => Box::new(xz_reader(reader).take(limit)),
+    })
+}
+
+/// Chunks of decompressed output kept in flight between the xz thread and its reader
+const XZ_PIPE_CHUNKS: usize = 4;
+
+/// lzma-rs only decompresses into a writer, so it runs on its own thread writing into a
+/// pipe. Once the reader is dropped its next write fails, which stops it.
+fn xz_reader(reader: impl Read + Send + 'static) -> PipeReader {
+    let (sender, chunks) = mpsc::sync_channel(XZ_PIPE_CHUNKS);
+    thread::spawn(move || {
+        let mut writer = PipeWriter(sender.clone());
+        if let Err(e) = lzma_rs::xz_decompress(&mut BufReader::new(reader), &mut writer) {
+            let _ = sender.send(Err(io::Error::other(e.to_string())));
+        }
+    });
+    PipeReader {
+        chunks,
+        current: io::Cursor::new(Vec::new()),
+        delivered: false,
+    }
+}
+
+struct PipeWriter(SyncSender<io::Result<Vec<u8>>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct PipeReader {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    current: io::Cursor<Vec<u8>>,
+    /// Whether any output came through, after which a decoding error reads as the end so
+    /// that a truncated file still shows what could be decompressed
+    delivered: bool,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.recv() {
+                Ok(Ok(chunk)) => {
+                    self.delivered = true;
+                    self.current = io::Cursor::new(chunk);
+                }
+                Ok(Err(e)) if !self.delivered => return Err(e),
+                Ok(Err(_)) | Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Lists archive members like `tar -tv`: size, modification time and path
+pub fn archive_preview(listing: &ArchiveListing) -> Text<'static> {
+    let dir_style = Style::default()
+        .fg(Color::Blue)
+        .add_modifier(Modifier::BOLD);
+    let dim = Style::default().fg(Color::DarkGray);
+
+    let (mut dir_count, mut file_count, mut total_size) = (0usize, 0usize, 0u64);
+    let mut lines: Vec<Line<'static>> = listing
+        .entries
+        .iter()
+        .map(|entry| {
+            let size = if entry.is_dir {
+                dir_count += 1;
+                "-".to_string()
+            } else {
+                file_count += 1;
+                total_size += entry.size;
+                human_size(entry.size)
+            };
+            let modified = entry.modified.as_deref().unwrap_or("");
+            let name = if entry.is_dir {
+                Span::styled(entry.path.clone(), dir_style)
+            } else {
+                Span::raw(entry.path.clone())
+            };
+            Line::from(vec![
+                Span::styled(format!("{size:>8}  {modified:<16}  "), dim),
+                name,
+            ])
+        })
+        .collect();
+
+    lines.push(Line::from(""));
+    let mut summary = format!(
+        "{dir_count} directories, {file_count} files, {} uncompressed",
+        human_size(total_size)
+    );
+    if listing.truncated {
+        summary.push_str(" (truncated)");
+    }
+    lines.push(Line::from(Span::styled(summary, dim)));
+
+    Text::from(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+
+    /// A tarball holding a `docs/` directory and `files`
+    fn tar_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut dir = tar::Header::new_gnu();
+        dir.set_entry_type(tar::EntryType::Directory);
+        dir.set_size(0);
+        dir.set_mode(0o755);
+        builder.append_data(&mut dir, "docs/", io::empty()).unwrap();
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_700_000_000);
+            builder.append_data(&mut header, name, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("docs/", options).unwrap();
+        for (name, contents) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zstd(bytes: &[u8]) -> Vec<u8> {
+        ruzstd::encoding::compress_to_vec(bytes, ruzstd::encoding::CompressionLevel::Fastest)
+    }
+
+    fn xz(bytes: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        lzma_rs::xz_compress(&mut &bytes[..], &mut compressed).unwrap();
+        compressed
+    }
+
+    const FILES: [(&str, &[u8]); 2] = [("docs/a.txt", b"alpha\n"), ("b.bin", &[0; 2048])];
+
+    fn listed(listing: &ArchiveListing) -> Vec<(&str, u64, bool)> {
+        listing
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.size, entry.is_dir))
+            .collect()
+    }
+
+    #[test]
+    fn detects_archives_and_compressed_files_by_name() {
+        let kind = |name: &str| ArchiveKind::from_path(Path::new(name));
+        assert_eq!(kind("app.JAR"), Some(ArchiveKind::Zip));
+        assert_eq!(kind("src.tar"), Some(ArchiveKind::Tar(None)));
+        assert_eq!(
+            kind("src.tgz"),
+            Some(ArchiveKind::Tar(Some(Compression::Gzip)))
+        );
+        assert_eq!(
+            kind("src.tar.zst"),
+            Some(ArchiveKind::Tar(Some(Compression::Zstd)))
+        );
+        assert_eq!(
+            kind("src.tar.xz"),
+            Some(ArchiveKind::Tar(Some(Compression::Xz)))
+        );
+        assert_eq!(kind("notes.txt.gz"), None);
+        assert_eq!(kind("tar.gz"), None);
+
+        let compression = |name: &str| Compression::from_path(Path::new(name));
+        assert_eq!(compression("notes.txt.gz"), Some(Compression::Gzip));
+        assert_eq!(compression("notes.txt"), None);
+    }
+
+    #[test]
+    fn lists_every_kind_of_archive() {
+        let tar = tar_bytes(&FILES);
+        let dir = temp_dir_with(&[
+            ("a.zip", &zip_bytes(&FILES)),
+            ("a.tar", &tar),
+            ("a.tar.gz", &gzip(&tar)),
+            ("a.tar.zst", &zstd(&tar)),
+            ("a.tar.xz", &xz(&tar)),
+        ]);
+        let expected = [
+            ("docs/", 0, true),
+            ("docs/a.txt", 6, false),
+            ("b.bin", 2048, false),
+        ];
+
+        for name in ["a.zip", "a.tar", "a.tar.gz", "a.tar.zst", "a.tar.xz"] {
+            let path = dir.path().join(name);
+            let listing = list_archive(&path, ArchiveKind::from_path(&path).unwrap()).unwrap();
+            assert_eq!(listed(&listing), expected, "{name}");
+            assert!(!listing.truncated);
+        }
+    }
+
+    #[test]
+    fn long_archives_are_truncated() {
+        let names: Vec<String> = (0..=MAX_ARCHIVE_ENTRIES)
+            .map(|i| format!("{i}.txt"))
+            .collect();
+        let files: Vec<(&str, &[u8])> =
+            names.iter().map(|name| (name.as_str(), &b""[..])).collect();
+        let dir = temp_dir_with(&[("a.tar", &tar_bytes(&files))]);
+
+        let listing = list_archive(&dir.path().join("a.tar"), ArchiveKind::Tar(None)).unwrap();
+        assert_eq!(listing.entries.len(), MAX_ARCHIVE_ENTRIES);
+        assert!(listing.truncated);
+    }
+
+    #[test]
+    fn broken_archives_are_errors() {
+        let dir = temp_dir_with(&[("a.zip", b"not a zip"), ("a.tar.xz", b"not xz")]);
+        assert!(list_archive(&dir.path().join("a.zip"), ArchiveKind::Zip).is_err());
+        let xz = ArchiveKind::Tar(Some(Compression::Xz));
+        assert!(list_archive(&dir.path().join("a.tar.xz"), xz).is_err());
+    }
+
+    #[test]
+    fn decompresses_single_files_up_to_the_limit() {
+        let text = b"hello, compressed world\n".repeat(100);
+        let dir = temp_dir_with(&[
+            ("a.gz", &gzip(&text)),
+            ("a.zst", &zstd(&text)),
+            ("a.xz", &xz(&text)),
+        ]);
+        for (name, compression) in [
+            ("a.gz", Compression::Gzip),
+            ("a.zst", Compression::Zstd),
+            ("a.xz", Compression::Xz),
+        ] {
+            let path = dir.path().join(name);
+            assert_eq!(read_compressed(&path, compression, u64::MAX).unwrap(), text);
+            assert_eq!(read_compressed(&path, compression, 5).unwrap(), b"hello");
+        }
+    }
+
+    #[test]
+    fn listing_ends_with_a_summary() {
+        let dir = temp_dir_with(&[("a.tar", &tar_bytes(&FILES))]);
+        let listing = list_archive(&dir.path().join("a.tar"), ArchiveKind::Tar(None)).unwrap();
+        let lines = plain_lines(&archive_preview(&listing).lines);
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("       -  "));
+        assert!(lines[0].ends_with("  docs/"));
+        assert!(lines[2].starts_with("    2.0K  "));
+        assert_eq!(lines[4], "1 directories, 2 files, 2.0K uncompressed");
+    }
+
+    #[test]
+    fn member_paths_point_into_the_archive() {
+        let dir = temp_dir_with(&[("a.zip", &zip_bytes(&FILES)), ("b.zip", b"broken")]);
+        let archive = dir.path().join("a.zip");
+
+        // Directories are left out, as they are when walking the file system
+        let paths = member_paths(&archive, ArchiveKind::Zip);
+        let expected = [
+            PathBuf::from(format!("{}!/docs/a.txt", archive.display())),
+            PathBuf::from(format!("{}!/b.bin", archive.display())),
+        ];
+        assert_eq!(paths, expected);
+        assert!(member_paths(&dir.path().join("b.zip"), ArchiveKind::Zip).is_empty());
+
+        let member = ArchiveMember::from_path(&paths[0]).unwrap();
+        assert_eq!(member.archive, archive);
+        assert_eq!(member.kind, ArchiveKind::Zip);
+        assert_eq!(member.name, "docs/a.txt");
+        assert_eq!(member.path(), paths[0]);
+    }
+
+    #[test]
+    fn only_existing_archives_split_a_path() {
+        let dir = temp_dir_with(&[("x!/a.tar", &tar_bytes(&FILES)), ("notes!/a.txt", b"")]);
+        let path = |p: &str| dir.path().join(p);
+
+        // The first separator is part of a directory name
+        let member = ArchiveMember::from_path(&path("x!/a.tar!/docs/a.txt")).unwrap();
+        assert_eq!(member.archive, path("x!/a.tar"));
+        assert_eq!(member.name, "docs/a.txt");
+
+        assert!(ArchiveMember::from_path(&path("notes!/a.txt")).is_none());
+        assert!(ArchiveMember::from_path(&path("missing.zip!/a.txt")).is_none());
+    }
+
+    #[test]
+    fn reads_members_up_to_the_limit() {
+        let dir = temp_dir_with(&[
+            ("a.zip", &zip_bytes(&FILES)),
+            ("a.tar.gz", &gzip(&tar_bytes(&FILES))),
+        ]);
+        for name in ["a.zip", "a.tar.gz"] {
+            let archive = dir.path().join(name);
+            let member = |member: &str| ArchiveMember {
+                archive: archive.clone(),
+                kind: ArchiveKind::from_path(&archive).unwrap(),
+                name: member.to_string(),
+            };
+            assert_eq!(member("docs/a.txt").read(u64::MAX).unwrap(), b"alpha\n");
+            assert_eq!(member("docs/a.txt").read(2).unwrap(), b"al");
+            assert!(member("missing").read(u64::MAX).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn extracting_keeps_members_inside_the_extract_directory() {
+        // tar::Builder refuses names with `..` in them, so the header is filled in by hand
+        let mut header = tar::Header::new_old();
+        let name = b"../../escape.txt";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_size(7);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, &b"payload"[..]).unwrap();
+        let dir = temp_dir_with(&[("escape.tar", &builder.into_inner().unwrap())]);
+
+        let member = ArchiveMember {
+            archive: dir.path().join("escape.tar"),
+            kind: ArchiveKind::Tar(None),
+            name: "../../escape.txt".to_string(),
+        };
+        let extracted = member.extract().unwrap();
+        assert_eq!(extracted, extract_dir().join("escape.tar/escape.txt"));
+        assert_eq!(std::fs::read(&extracted).unwrap(), b"payload");
+        std::fs::remove_file(extracted).unwrap();
+    }
+}
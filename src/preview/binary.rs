@@ -0,0 +1,249 @@
+use object::elf::DT_NEEDED;
+use object::read::ReadCache;
+use object::read::elf::{ElfFile, ElfFile32, ElfFile64, FileHeader};
+use object::{
+    BinaryFormat, Endianness, FileKind, Object, ObjectKind, ObjectSection, ReadRef, SectionKind,
+};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::Path;
+
+use crate::filesystem::human_size;
+
+/// Exported symbols beyond this many are only counted
+const MAX_LISTED_SYMBOLS: usize = 100;
+
+/// Summarizes an ELF, Mach-O or PE file: its format and architecture, whether it carries
+/// debug info, the libraries it links against, its sections and its exported symbols.
+/// `None` if the file isn't one of those.
+pub fn object_summary(path: &Path) -> Option<Text<'static>> {
+    // Only the headers and tables that are looked at get read, not the whole binary
+    let cache = ReadCache::new(File::open(path).ok()?);
+    let data = &cache;
+    let kind = FileKind::parse(data).ok()?;
+    let file = object::File::parse(data).ok()?;
+
+    let heading = Style::default().add_modifier(Modifier::BOLD);
+    let dim = Style::default().fg(Color::DarkGray);
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{name:<14}"), dim),
+            Span::raw(value),
+        ])
+    };
+
+    let mut lines = vec![
+        field("Format", describe(&file)),
+        field("Architecture", format!("{:?}", file.architecture())),
+    ];
+    if file.entry() != 0 {
+        lines.push(field("Entry point", format!("{:#x}", file.entry())));
+    }
+    lines.extend([
+        field(
+            "Debug info",
+            if file.has_debug_symbols() {
+                "yes".to_string()
+            } else {
+                "no".to_string()
+            },
+        ),
+        field(
+            "Symbol table",
+            match file.symbol_table() {
+                Some(_) => "present".to_string(),
+                None => "stripped".to_string(),
+            },
+        ),
+    ]);
+    if let Ok(Some(build_id)) = file.build_id() {
+        let hex: String = build_id.iter().map(|b| format!("{b:02x}")).collect();
+        lines.push(field("Build ID", hex));
+    }
+
+    let dependencies = match kind {
+        FileKind::Elf32 => ElfFile32::<Endianness, _>::parse(data)
+            .map(|elf| elf_needed(&elf))
+            .ok(),
+        FileKind::Elf64 => ElfFile64::<Endianness, _>::parse(data)
+            .map(|elf| elf_needed(&elf))
+            .ok(),
+        _ => file.imports().ok().map(|imports| {
+            imports
+                .iter()
+                .map(|import| String::from_utf8_lossy(import.library()).into_owned())
+                .filter(|library| !library.is_empty())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        }),
+    }
+    .unwrap_or_default();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        format!("Dependencies ({})", dependencies.len()),
+        heading,
+    )));
+    lines.extend(
+        dependencies
+            .into_iter()
+            .map(|dep| Line::from(format!("  {dep}"))),
+    );
+
+    let sections: Vec<_> = file.sections().collect();
+    let name_width = sections
+        .iter()
+        .filter_map(|section| section.name().ok())
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        format!("Sections ({})", sections.len()),
+        heading,
+    )));
+    for section in &sections {
+        let name = section.name().unwrap_or("?");
+        lines.push(Line::from(vec![
+            Span::raw(format!("  {name:<name_width$}  ")),
+            Span::styled(
+                format!(
+                    "{:<9} {:>8}  {:#x}",
+                    section_kind(section.kind()),
+                    human_size(section.size()),
+                    section.address()
+                ),
+                dim,
+            ),
+        ]));
+    }
+
+    let exports = file.exports().unwrap_or_default();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        format!("Exported symbols ({})", exports.len()),
+        heading,
+    )));
+    for export in exports.iter().take(MAX_LISTED_SYMBOLS) {
+        lines.push(Line::from(format!(
+            "  {}",
+            String::from_utf8_lossy(export.name())
+        )));
+    }
+    if exports.len() > MAX_LISTED_SYMBOLS {
+        lines.push(Line::from(Span::styled(
+            format!("  ... {} more", exports.len() - MAX_LISTED_SYMBOLS),
+            dim,
+        )));
+    }
+
+    Some(Text::from(lines))
+}
+
+/// e.g. "ELF 64-bit little-endian shared library"
+fn describe<'data, R: ReadRef<'data>>(file: &object::File<'data, R>) -> String {
+    let format = match file.format() {
+        BinaryFormat::Elf => "ELF",
+        BinaryFormat::MachO => "Mach-O",
+        BinaryFormat::Pe => "PE",
+        BinaryFormat::Coff => "COFF",
+        BinaryFormat::Wasm => "Wasm",
+        BinaryFormat::Xcoff => "XCOFF",
+        _ => "object",
+    };
+    let kind = match file.kind() {
+        ObjectKind::Executable => "executable",
+        // Position independent executables are shared objects that ask for an interpreter
+        ObjectKind::Dynamic if file.section_by_name(".interp").is_some() => {
+            "position-independent executable"
+        }
+        ObjectKind::Dynamic => "shared library",
+        ObjectKind::Relocatable => "object file",
+        ObjectKind::Core => "core dump",
+        _ => "binary",
+    };
+    format!(
+        "{format} {}-bit {}-endian {kind}",
+        if file.is_64() { 64 } else { 32 },
+        if file.is_little_endian() {
+            "little"
+        } else {
+            "big"
+        }
+    )
+}
+
+fn section_kind(kind: SectionKind) -> &'static str {
+    match kind {
+        SectionKind::Text => "code",
+        SectionKind::Data => "data",
+        SectionKind::ReadOnlyData | SectionKind::ReadOnlyDataWithRel => "rodata",
+        SectionKind::ReadOnlyString | SectionKind::OtherString => "strings",
+        SectionKind::UninitializedData | SectionKind::Common => "bss",
+        SectionKind::Tls | SectionKind::UninitializedTls | SectionKind::TlsVariables => "tls",
+        SectionKind::Note => "note",
+        SectionKind::Debug | SectionKind::DebugString => "debug",
+        SectionKind::Metadata | SectionKind::Linker => "metadata",
+        _ => "other",
+    }
+}
+
+/// The `DT_NEEDED` libraries of a dynamically linked ELF file
+fn elf_needed<'data, Elf: FileHeader, R: ReadRef<'data>>(
+    elf: &ElfFile<'data, Elf, R>,
+) -> Vec<String> {
+    let Ok(table) = elf
+        .elf_section_table()
+        .dynamic_table(elf.endian(), elf.data())
+    else {
+        return Vec::new();
+    };
+    (&table)
+        .into_iter()
+        .filter(|entry| entry.tag == DT_NEEDED)
+        .filter_map(|entry| table.string(entry).ok())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn summarizes_the_running_test_binary() {
+        let text = object_summary(&std::env::current_exe().unwrap()).unwrap();
+        let lines = plain_lines(&text.lines);
+
+        assert!(lines[0].starts_with("Format        ELF 64-bit little-endian"));
+        assert!(lines.iter().any(|line| line.starts_with("Dependencies (")));
+        assert!(lines.iter().any(|line| line.trim().starts_with("libc.so")));
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.trim_start().starts_with(".text "))
+        );
+    }
+
+    #[test]
+    fn other_files_have_no_summary() {
+        let dir = temp_dir_with(&[("a.bin", &[0x7f, b'E', b'L', b'F', 0, 0]), ("b.txt", b"hi")]);
+        assert!(object_summary(&dir.path().join("a.bin")).is_none());
+        assert!(object_summary(&dir.path().join("b.txt")).is_none());
+        assert!(object_summary(&dir.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn section_kinds_have_short_names() {
+        assert_eq!(section_kind(SectionKind::Text), "code");
+        assert_eq!(section_kind(SectionKind::UninitializedData), "bss");
+        assert_eq!(section_kind(SectionKind::DebugString), "debug");
+        assert_eq!(section_kind(SectionKind::Unknown), "other");
+    }
+}
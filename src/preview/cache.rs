@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::preview::Preview;
+use crate::preview::archive::ArchiveMember;
+
+/// Identifies one rendering of a file. A preview is only reused while the file is
+/// unchanged on disk and the pane it was rendered for has the same size.
+/// `query` and `line` are only filled in when the preview depends on them, as with `{q}`
+/// and `{line}` commands. `lines` is how much of a file's text is rendered, which grows
+/// as the cursor moves down a focused preview or a search jumps past the end of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub path: PathBuf,
+    pub query: String,
+    pub line: usize,
+    pub lines: usize,
+    pub mtime: Option<SystemTime>,
+    pub len: u64,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl CacheKey {
+    pub fn new(
+        path: &Path,
+        query: &str,
+        line: usize,
+        lines: usize,
+        width: u16,
+        height: u16,
+    ) -> Self {
+        // Members of an archive change whenever the archive does
+        let metadata = std::fs::metadata(path).ok().or_else(|| {
+            let member = ArchiveMember::from_path(path)?;
+            std::fs::metadata(member.archive).ok()
+        });
+        CacheKey {
+            path: path.to_path_buf(),
+            query: query.to_string(),
+            line,
+            lines,
+            mtime: metadata.as_ref().and_then(|m| m.modified().ok()),
+            len: metadata.map(|m| m.len()).unwrap_or(0),
+            width,
+            height,
+        }
+    }
+}
+
+struct Entry {
+    preview: Arc<Preview>,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Least-recently-used cache of rendered previews, bounded by an estimate of the memory
+/// they take up rather than by entry count, since one minified file can outweigh
+/// hundreds of small ones.
+pub struct PreviewCache {
+    entries: HashMap<CacheKey, Entry>,
+    budget: usize,
+    used: usize,
+    clock: u64,
+}
+
+impl PreviewCache {
+    pub fn new(budget: usize) -> Self {
+        PreviewCache {
+            entries: HashMap::new(),
+            budget,
+            used: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<Arc<Preview>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.preview.clone())
+    }
+
+    /// Checks for an entry without counting it as a use
+    pub fn contains(&self, key: &CacheKey) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: CacheKey, preview: Arc<Preview>) {
+        self.clock += 1;
+        let bytes = preview.approx_bytes();
+        if let Some(old) = self.entries.insert(
+            key,
+            Entry {
+                preview,
+                bytes,
+                last_used: self.clock,
+            },
+        ) {
+            self.used -= old.bytes;
+        }
+        self.used += bytes;
+        self.evict();
+    }
+
+    /// Drops every rendering of `path`, whatever size or mtime it was cached under
+    pub fn invalidate(&mut self, path: &Path) {
+        self.entries.retain(|key, entry| {
+            let keep = key.path != path;
+            if !keep {
+                self.used -= entry.bytes;
+            }
+            keep
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    /// Evicts least recently used entries until the cache fits its budget again.
+    /// The newest entry is always kept, even if it alone is over budget.
+    fn evict(&mut self) {
+        while self.used > self.budget && self.entries.len() > 1 {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.used -= entry.bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::MAX_PREVIEW_LINES;
+    use crate::test_util::temp_dir_with;
+    use ratatui::text::Text;
+    use std::fs;
+
+    fn key(name: &str) -> CacheKey {
+        CacheKey::new(Path::new(name), "", 1, MAX_PREVIEW_LINES, 80, 24)
+    }
+
+    fn preview(bytes: usize) -> Arc<Preview> {
+        Arc::new(Preview::listing(Text::from("x".repeat(bytes))))
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_first() {
+        let size = preview(100).approx_bytes();
+        let mut cache = PreviewCache::new(size * 3);
+        cache.insert(key("a"), preview(100));
+        cache.insert(key("b"), preview(100));
+        cache.insert(key("c"), preview(100));
+        assert!(cache.get(&key("a")).is_some());
+
+        cache.insert(key("d"), preview(100));
+        assert!(!cache.contains(&key("b")));
+        assert!(cache.contains(&key("a")));
+        assert!(cache.contains(&key("c")));
+        assert!(cache.contains(&key("d")));
+    }
+
+    #[test]
+    fn stays_within_its_budget() {
+        let mut cache = PreviewCache::new(5_000);
+        for i in 0..100 {
+            cache.insert(key(&i.to_string()), preview(300 + i));
+            assert!(cache.used <= 5_000);
+            assert_eq!(
+                cache.used,
+                cache
+                    .entries
+                    .values()
+                    .map(|entry| entry.bytes)
+                    .sum::<usize>()
+            );
+        }
+
+        // Replacing an entry doesn't count it twice
+        cache.insert(key("99"), preview(10));
+        assert_eq!(
+            cache.used,
+            cache
+                .entries
+                .values()
+                .map(|entry| entry.bytes)
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn keeps_a_single_entry_over_budget() {
+        let mut cache = PreviewCache::new(10);
+        cache.insert(key("big"), preview(1_000));
+        assert!(cache.contains(&key("big")));
+    }
+
+    #[test]
+    fn invalidate_drops_every_rendering_of_a_path() {
+        let mut cache = PreviewCache::new(1 << 20);
+        cache.insert(
+            CacheKey::new(Path::new("a"), "", 1, MAX_PREVIEW_LINES, 80, 24),
+            preview(10),
+        );
+        cache.insert(
+            CacheKey::new(Path::new("a"), "", 1, MAX_PREVIEW_LINES, 100, 40),
+            preview(10),
+        );
+        cache.insert(key("b"), preview(10));
+        cache.invalidate(Path::new("a"));
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.used, cache.entries[&key("b")].bytes);
+    }
+
+    #[test]
+    fn key_changes_with_the_file_and_pane() {
+        let dir = temp_dir_with(&[("a.txt", b"one\n")]);
+        let path = dir.path().join("a.txt");
+        let before = CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 80, 24);
+        assert_eq!(
+            before,
+            CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 80, 24)
+        );
+        assert_ne!(
+            before,
+            CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 81, 24)
+        );
+        assert_ne!(
+            before,
+            CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 80, 25)
+        );
+
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let grown = CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 80, 24);
+        assert_ne!(before.len, grown.len);
+        assert_ne!(before, grown);
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        let earlier = before.mtime.unwrap() - std::time::Duration::from_secs(60);
+        file.set_modified(earlier).unwrap();
+        let touched = CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 80, 24);
+        assert_eq!(touched.len, grown.len);
+        assert_ne!(touched, grown);
+    }
+}
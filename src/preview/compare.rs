@@ -0,0 +1,254 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use similar::{Algorithm, DiffOp, DiffTag, capture_diff_slices, group_diff_ops};
+use std::path::Path;
+use unicode_width::UnicodeWidthChar;
+
+use crate::highlight::highlight_lines;
+use crate::preview::{Preview, PreviewOptions, error_preview, read_text};
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Files are compared up to this many lines
+const MAX_COMPARE_LINES: usize = 5000;
+
+/// Separates the two sides of a side-by-side comparison
+const SIDE_SEPARATOR: &str = " │ ";
+
+/// Diffs `path` against `base`, either as a unified diff or side by side in `width`
+/// columns. Both sides keep their syntax highlighting, with removed and added lines
+/// tinted red and green.
+pub fn compare_preview(base: &Path, path: &Path, opts: &PreviewOptions, width: u16) -> Preview {
+    let (Some((old, _)), Some((new, _))) = (read_text(base), read_text(path)) else {
+        return error_preview("Can't compare: one of the files isn't text");
+    };
+    let old_lines: Vec<&str> = old.lines().take(MAX_COMPARE_LINES).collect();
+    let new_lines: Vec<&str> = new.lines().take(MAX_COMPARE_LINES).collect();
+
+    let ops = capture_diff_slices(Algorithm::Myers, &old_lines, &new_lines);
+    let hunks = group_diff_ops(ops, CONTEXT_LINES);
+
+    // Highlighting has to start at the top of a file for the syntax state to be right,
+    // but stops at the last line any hunk shows
+    let last_op = hunks.last().and_then(|hunk| hunk.last());
+    let old_end = last_op.map_or(0, |op| op.old_range().end);
+    let new_end = last_op.map_or(0, |op| op.new_range().end);
+    let old_text = highlight_lines(base, &old, opts, old_end);
+    let new_text = highlight_lines(path, &new, opts, new_end);
+
+    let sides = Sides {
+        old: &old_text.lines,
+        new: &new_text.lines,
+        digits: old_lines
+            .len()
+            .max(new_lines.len())
+            .max(1)
+            .to_string()
+            .len(),
+        removed: opts.color_depth.rgb(80, 25, 25),
+        added: opts.color_depth.rgb(25, 70, 25),
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("--- {}", base.display()),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(
+            format!("+++ {}", path.display()),
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        )),
+    ];
+    if hunks.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "Files are identical",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    for hunk in &hunks {
+        lines.push(hunk_header(hunk));
+        for op in hunk {
+            if opts.side_by_side {
+                sides.side_by_side(op, width as usize, &mut lines);
+            } else {
+                sides.unified(op, &mut lines);
+            }
+        }
+    }
+
+    let mut text = Text::from(lines);
+    text.style = new_text.style;
+    Preview::listing(text).with_label("compare")
+}
+
+/// The highlighted lines of both files, the width of their line numbers and the tints
+/// for changed lines
+struct Sides<'a> {
+    old: &'a [Line<'static>],
+    new: &'a [Line<'static>],
+    digits: usize,
+    removed: Color,
+    added: Color,
+}
+
+impl Sides<'_> {
+    fn unified(&self, op: &DiffOp, out: &mut Vec<Line<'static>>) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            for i in old_range {
+                out.push(prefixed(" ", &line_at(self.old, i), None));
+            }
+            return;
+        }
+        for i in old_range {
+            out.push(prefixed("-", &line_at(self.old, i), Some(self.removed)));
+        }
+        for i in new_range {
+            out.push(prefixed("+", &line_at(self.new, i), Some(self.added)));
+        }
+    }
+
+    fn side_by_side(&self, op: &DiffOp, width: usize, out: &mut Vec<Line<'static>>) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let column = width.saturating_sub(SIDE_SEPARATOR.chars().count()) / 2;
+        let (old_tint, new_tint) = match tag {
+            DiffTag::Equal => (None, None),
+            _ => (Some(self.removed), Some(self.added)),
+        };
+        let rows = old_range.len().max(new_range.len());
+        for row in 0..rows {
+            let old = (row < old_range.len())
+                .then(|| old_range.start + row)
+                .map(|i| (i, line_at(self.old, i), old_tint));
+            let new = (row < new_range.len())
+                .then(|| new_range.start + row)
+                .map(|i| (i, line_at(self.new, i), new_tint));
+            let mut spans = side(old, self.digits, column);
+            spans.push(Span::styled(
+                SIDE_SEPARATOR,
+                Style::default().fg(Color::DarkGray),
+            ));
+            spans.extend(side(new, self.digits, column));
+            out.push(Line::from(spans));
+        }
+    }
+}
+
+/// "@@ -start,len +start,len @@" for a group of changes and their context
+fn hunk_header(hunk: &[DiffOp]) -> Line<'static> {
+    let (Some(first), Some(last)) = (hunk.first(), hunk.last()) else {
+        return Line::default();
+    };
+    let old_start = first.old_range().start;
+    let new_start = first.new_range().start;
+    let old_len = last.old_range().end - old_start;
+    let new_len = last.new_range().end - new_start;
+    Line::from(Span::styled(
+        format!(
+            "@@ -{},{old_len} +{},{new_len} @@",
+            old_start + 1,
+            new_start + 1
+        ),
+        Style::default().fg(Color::Cyan),
+    ))
+}
+
+/// Line `i` of a highlighted file, blank if highlighting didn't get that far
+fn line_at(lines: &[Line<'static>], i: usize) -> Line<'static> {
+    lines.get(i).cloned().unwrap_or_default()
+}
+
+fn prefixed(prefix: &'static str, line: &Line<'static>, tint: Option<Color>) -> Line<'static> {
+    let mut spans = vec![Span::raw(prefix)];
+    spans.extend(line.spans.iter().cloned());
+    if let Some(tint) = tint {
+        for span in &mut spans {
+            span.style = span.style.bg(tint);
+        }
+    }
+    Line::from(spans)
+}
+
+/// One half of a side-by-side row: line number and content, cut or padded to `width`
+fn side(
+    line: Option<(usize, Line<'static>, Option<Color>)>,
+    digits: usize,
+    width: usize,
+) -> Vec<Span<'static>> {
+    let Some((index, line, tint)) = line else {
+        return vec![Span::raw(" ".repeat(width))];
+    };
+    let number = format!("{:>digits$} ", index + 1);
+    let room = width.saturating_sub(number.len());
+    let mut spans = vec![Span::styled(number, Style::default().fg(Color::DarkGray))];
+
+    let mut used = 0;
+    let mut last_style = Style::default();
+    for span in &line.spans {
+        let mut content = String::new();
+        for ch in span.content.chars() {
+            let ch_width = ch.width().unwrap_or(0);
+            if used + ch_width > room {
+                break;
+            }
+            content.push(ch);
+            used += ch_width;
+        }
+        last_style = span.style;
+        spans.push(Span::styled(content, span.style));
+    }
+    spans.push(Span::styled(" ".repeat(room - used), last_style));
+    if let Some(tint) = tint {
+        for span in &mut spans[1..] {
+            span.style = span.style.bg(tint);
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+
+    /// The diff lines of comparing `old` with `new`, after the two file name headers
+    fn compared(old: &str, new: &str, side_by_side: bool) -> Vec<String> {
+        let dir = temp_dir_with(&[("old.txt", old.as_bytes()), ("new.txt", new.as_bytes())]);
+        let opts = PreviewOptions {
+            side_by_side,
+            ..PreviewOptions::for_tests()
+        };
+        let preview = compare_preview(
+            &dir.path().join("old.txt"),
+            &dir.path().join("new.txt"),
+            &opts,
+            80,
+        );
+        plain_lines(&preview.text.lines[2..])
+    }
+
+    #[test]
+    fn unified_shows_changes_with_context() {
+        let lines = compared("a\nb\nc\n", "a\nB\nc\n", false);
+        assert_eq!(lines, ["@@ -1,3 +1,3 @@", " a", "-b", "+B", " c"]);
+    }
+
+    #[test]
+    fn trailing_blank_line() {
+        let lines = compared("a\n\n", "a\nb\n\n", false);
+        assert_eq!(lines, ["@@ -1,2 +1,3 @@", " a", "+b", " "]);
+        let lines = compared("a\n", "a\n\n", true);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].trim_start().starts_with("│"));
+    }
+
+    #[test]
+    fn identical_files() {
+        let lines = compared("same\n", "same\n", false);
+        assert_eq!(lines, ["Files are identical"]);
+    }
+}
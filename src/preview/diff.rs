@@ -0,0 +1,28 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+
+use crate::git::DiffLine;
+use crate::preview::expand_tabs;
+
+/// Colors a unified diff the way `git diff` does: bold file headers, cyan hunk headers,
+/// green additions and red deletions
+pub fn diff_preview(lines: &[DiffLine], tab_width: usize) -> Text<'static> {
+    let rendered: Vec<Line<'static>> = lines
+        .iter()
+        .map(|line| {
+            let content = expand_tabs(&line.content, tab_width);
+            let (prefix, style) = match line.origin {
+                'F' => ("", Style::default().add_modifier(Modifier::BOLD)),
+                'H' => ("", Style::default().fg(Color::Cyan)),
+                '+' | '>' => ("+", Style::default().fg(Color::Green)),
+                '-' | '<' => ("-", Style::default().fg(Color::Red)),
+                _ => (" ", Style::default()),
+            };
+            Line::from(Span::styled(format!("{prefix}{content}"), style))
+        })
+        .collect();
+
+    Text::from(rendered)
+}
@@ -0,0 +1,127 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+/// Decodes file contents to UTF-8, working out the encoding from a byte order mark,
+/// failing that from UTF-8 validity, and failing that from chardetng's guess.
+/// Returns `None` for data that looks binary rather than like text in any encoding.
+pub fn decode(bytes: &[u8]) -> Option<(String, &'static Encoding)> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return Some((text.into_owned(), encoding));
+    }
+
+    if bytes.contains(&0) {
+        // UTF-16 without a BOM is mostly zero bytes in every other position, anything else
+        // with NULs in it is binary
+        let encoding = sniff_utf16(bytes)?;
+        let (text, _) = encoding.decode_without_bom_handling(bytes);
+        return Some((text.into_owned(), encoding));
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => return Some((text.to_string(), UTF_8)),
+        // Only the last character is incomplete, the read limit cut it off
+        Err(e) if e.error_len().is_none() => {
+            let text = std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default();
+            return Some((text.to_string(), UTF_8));
+        }
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    let (text, _, _) = encoding.decode(bytes);
+    Some((text.into_owned(), encoding))
+}
+
+/// Guesses the byte order of BOM-less UTF-16 by which half of each pair is usually zero,
+/// as it is for ASCII-range text
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let pairs = bytes.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+    let even_zeros = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+    // Require most pairs to look like ASCII-range UTF-16 so that binaries don't pass
+    if odd_zeros * 10 >= pairs * 7 && even_zeros * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 >= pairs * 7 && odd_zeros * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn byte_order_marks_win() {
+        assert_eq!(decode(b"\xef\xbb\xbfhi"), Some(("hi".to_string(), UTF_8)));
+        let mut le = vec![0xff, 0xfe];
+        le.extend(utf16("hé", false));
+        assert_eq!(decode(&le), Some(("hé".to_string(), UTF_16LE)));
+        let mut be = vec![0xfe, 0xff];
+        be.extend(utf16("hé", true));
+        assert_eq!(decode(&be), Some(("hé".to_string(), UTF_16BE)));
+    }
+
+    #[test]
+    fn utf16_without_a_bom_is_sniffed() {
+        let text = "fn main() {}\n";
+        assert_eq!(
+            decode(&utf16(text, false)),
+            Some((text.to_string(), UTF_16LE))
+        );
+        assert_eq!(
+            decode(&utf16(text, true)),
+            Some((text.to_string(), UTF_16BE))
+        );
+    }
+
+    #[test]
+    fn nul_bytes_otherwise_mean_binary() {
+        assert_eq!(
+            decode(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0\x03\0>\0"),
+            None
+        );
+        assert_eq!(decode(b"\0"), None);
+    }
+
+    #[test]
+    fn utf8_cut_off_inside_the_last_character() {
+        let bytes = "ab✓".as_bytes();
+        for cut in bytes.len() - 2..bytes.len() {
+            assert_eq!(decode(&bytes[..cut]), Some(("ab".to_string(), UTF_8)));
+        }
+        assert_eq!(decode(bytes), Some(("ab✓".to_string(), UTF_8)));
+    }
+
+    #[test]
+    fn invalid_utf8_in_the_middle_falls_back_to_detection() {
+        let (text, encoding) = decode(b"caf\xe9 cr\xe8me br\xfbl\xe9e").unwrap();
+        assert_eq!(encoding, WINDOWS_1252);
+        assert_eq!(text, "café crème brûlée");
+
+        let (text, encoding) = decode(&SHIFT_JIS.encode("これは日本語のテキストです").0).unwrap();
+        assert_eq!(encoding, SHIFT_JIS);
+        assert_eq!(text, "これは日本語のテキストです");
+    }
+}
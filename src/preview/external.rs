@@ -0,0 +1,187 @@
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::preview::MAX_PREVIEW_BYTES;
+use crate::preview::cache::CacheKey;
+
+/// How often a running preview command is checked for completion, timeout or cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Why a preview command produced no output worth showing
+pub enum CommandError {
+    /// The selection moved on before the command finished
+    Cancelled,
+    /// Spawning failed, the command timed out or it exited unsuccessfully.
+    /// Holds a message for the preview pane.
+    Failed(String),
+}
+
+/// Substitutes the placeholders in a `--preview` style command line:
+/// `{}` for the path, `{q}` for the current query and `{line}` for the line in focus.
+/// Values are shell-quoted so that odd file names can't break out of the command.
+pub fn expand_command(template: &str, path: &Path, query: &str, line: usize) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        if let Some(after) = rest.strip_prefix("{}") {
+            expanded.push_str(&shell_quote(&path.to_string_lossy()));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{q}") {
+            expanded.push_str(&shell_quote(query));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{line}") {
+            expanded.push_str(&line.to_string());
+            rest = after;
+        } else {
+            expanded.push('{');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Whether the command's output depends on the query, so previews must be cached per query
+pub fn uses_query(template: &str) -> bool {
+    template.contains("{q}")
+}
+
+/// Whether the command's output depends on the line in focus, so previews must be cached
+/// per line
+pub fn uses_line(template: &str) -> bool {
+    template.contains("{line}")
+}
+
+/// Runs a preview command through the shell and returns what it printed. The command is
+/// killed once `timeout` passes or as soon as `cancelled` returns true.
+pub fn run_preview_command(
+    template: &str,
+    key: &CacheKey,
+    timeout: Duration,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Vec<u8>, CommandError> {
+    let command_line = expand_command(template, &key.path, &key.query, key.line);
+
+    let mut child = shell(&command_line)
+        .env("FINDR_PREVIEW_COLUMNS", key.width.to_string())
+        .env("FINDR_PREVIEW_LINES", key.height.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CommandError::Failed(format!("{command_line}: {e}")))?;
+
+    // Drain the pipes on their own threads so a chatty command can't block on a full pipe
+    let stdout = child.stdout.take().map(read_to_end_in_background);
+    let stderr = child.stderr.take().map(read_to_end_in_background);
+
+    let started = Instant::now();
+    let status = loop {
+        if let Ok(Some(status)) = child.try_wait() {
+            break status;
+        }
+        if cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CommandError::Cancelled);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CommandError::Failed(format!(
+                "{command_line}: timed out after {}ms",
+                timeout.as_millis()
+            )));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let stdout = stdout.and_then(|h| h.join().ok()).unwrap_or_default();
+    if status.success() {
+        return Ok(stdout);
+    }
+
+    let stderr = stderr.and_then(|h| h.join().ok()).unwrap_or_default();
+    Err(CommandError::Failed(format!(
+        "{command_line}: {status}\n{}",
+        String::from_utf8_lossy(&stderr)
+    )))
+}
+
+/// Keeps at most `MAX_PREVIEW_BYTES` of the output but reads everything, so that the
+/// command isn't left blocked on a full pipe until it times out
+fn read_to_end_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 8192];
+        while let Ok(n) = pipe.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            let room = (MAX_PREVIEW_BYTES as usize).saturating_sub(out.len());
+            out.extend_from_slice(&chunk[..n.min(room)]);
+        }
+        out
+    })
+}
+
+#[cfg(unix)]
+fn shell(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
+
+#[cfg(windows)]
+fn shell(command_line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(command_line);
+    command
+}
+
+#[cfg(unix)]
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_every_placeholder() {
+        let expanded = expand_command("bat -H {line} {} --query {q}", Path::new("a.rs"), "fn", 12);
+        assert_eq!(expanded, "bat -H 12 'a.rs' --query 'fn'");
+    }
+
+    #[test]
+    fn quotes_awkward_paths() {
+        let expanded = expand_command("cat {}", Path::new("it's here.txt"), "", 1);
+        assert_eq!(expanded, r"cat 'it'\''s here.txt'");
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        let expanded = expand_command("awk '{print}' {} {", Path::new("x"), "", 1);
+        assert_eq!(expanded, "awk '{print}' 'x' {");
+    }
+
+    #[test]
+    fn detects_dependent_commands() {
+        assert!(uses_query("rg {q} {}"));
+        assert!(!uses_query("cat {}"));
+        assert!(uses_line("bat -H {line} {}"));
+        assert!(!uses_line("bat {}"));
+    }
+}
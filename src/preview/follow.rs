@@ -0,0 +1,224 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::collections::VecDeque;
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::preview::{Preview, PreviewOptions, style_contents};
+
+/// Lines kept from the end of a followed file
+const MAX_FOLLOW_LINES: usize = 1000;
+
+/// How far back from the end a file is read when following starts
+const FOLLOW_TAIL_BYTES: u64 = 256 * 1024;
+
+/// The end of a file that keeps growing, like `tail -f`. Each poll reads only what was
+/// appended since the last one; when the file shrinks (truncated) or the path points to a
+/// new file (rotated), it starts over from the beginning of the new contents.
+pub struct Follower {
+    path: PathBuf,
+    file: File,
+    /// Bytes of the file consumed so far, always up to the end of a complete line
+    offset: u64,
+    /// Bytes of the file read so far, including a last line still being written
+    read_to: u64,
+    lines: VecDeque<Line<'static>>,
+    /// The last of `lines` has no newline yet, so it is replaced on the next poll
+    partial: bool,
+    style: Style,
+}
+
+impl Follower {
+    pub fn open(path: &Path, opts: &PreviewOptions) -> io::Result<Self> {
+        let file = File::open(path)?;
+        if !file.metadata()?.is_file() {
+            return Err(io::Error::other("not a regular file"));
+        }
+        let len = file.metadata()?.len();
+        let offset = len.saturating_sub(FOLLOW_TAIL_BYTES);
+        let mut follower = Follower {
+            path: path.to_path_buf(),
+            file,
+            offset,
+            read_to: offset,
+            lines: VecDeque::new(),
+            partial: false,
+            style: Style::default(),
+        };
+        let skip_partial_line = follower.offset > 0;
+        follower.read_appended(opts, skip_partial_line)?;
+        Ok(follower)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Picks up whatever was written since the last poll. Returns whether anything changed.
+    pub fn poll(&mut self, opts: &PreviewOptions) -> io::Result<bool> {
+        let current = std::fs::metadata(&self.path)?;
+        if !same_file(&current, &self.file.metadata()?) {
+            self.file = File::open(&self.path)?;
+            self.restart("file rotated");
+        } else if current.len() < self.read_to {
+            self.restart("file truncated");
+        } else if current.len() == self.read_to {
+            return Ok(false);
+        }
+        self.read_appended(opts, false)?;
+        Ok(true)
+    }
+
+    pub fn preview(&self) -> Preview {
+        let mut text = Text::from(Vec::from(self.lines.clone()));
+        text.style = self.style;
+        Preview::listing(text).with_label("follow")
+    }
+
+    fn restart(&mut self, reason: &str) {
+        self.drop_partial();
+        self.offset = 0;
+        self.read_to = 0;
+        self.push_line(Line::from(Span::styled(
+            format!("── {reason} ──"),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+        )));
+    }
+
+    /// Reads from `offset` to the end. Complete lines are consumed, and a last line without
+    /// a newline yet is shown until the next read replaces it.
+    fn read_appended(&mut self, opts: &PreviewOptions, skip_partial_line: bool) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = Vec::new();
+        self.file.by_ref().read_to_end(&mut bytes)?;
+        self.read_to = self.offset + bytes.len() as u64;
+        self.offset += bytes
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |last_newline| last_newline as u64 + 1);
+        self.drop_partial();
+
+        let mut content = String::from_utf8_lossy(&bytes).into_owned();
+        if skip_partial_line && let Some(newline) = content.find('\n') {
+            content.drain(..=newline);
+        }
+        let new_lines: Vec<&str> = content.lines().collect();
+        let kept = &new_lines[new_lines.len().saturating_sub(MAX_FOLLOW_LINES)..];
+        // Styled in one go so that highlighting carries over from line to line
+        let (text, _) = style_contents(&self.path, &kept.join("\n"), opts, kept.len());
+        self.style = text.style;
+        for line in text.lines {
+            self.push_line(line);
+        }
+        self.partial = !content.is_empty() && !content.ends_with('\n');
+        Ok(())
+    }
+
+    /// Takes back the shown part of a line still being written, as it's about to be read
+    /// again in full
+    fn drop_partial(&mut self) {
+        if self.partial {
+            self.lines.pop_back();
+            self.partial = false;
+        }
+    }
+
+    fn push_line(&mut self, line: Line<'static>) {
+        if self.lines.len() == MAX_FOLLOW_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    // Without inodes a rotated file is only noticed once it is shorter than before
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+    use std::fs;
+
+    fn shown(follower: &Follower) -> Vec<String> {
+        plain_lines(&follower.preview().text.lines)
+    }
+
+    fn append(path: &Path, contents: &str) {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        std::io::Write::write_all(&mut file, contents.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn picks_up_appended_lines() {
+        let opts = PreviewOptions::for_tests();
+        let dir = temp_dir_with(&[("app.log", b"one\n")]);
+        let path = dir.path().join("app.log");
+        let mut follower = Follower::open(&path, &opts).unwrap();
+        assert!(!follower.poll(&opts).unwrap());
+
+        append(&path, "two\nthree\n");
+        assert!(follower.poll(&opts).unwrap());
+        assert_eq!(shown(&follower), ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn shows_a_partial_line_until_it_is_finished() {
+        let opts = PreviewOptions::for_tests();
+        let dir = temp_dir_with(&[("app.log", b"one\ntw")]);
+        let path = dir.path().join("app.log");
+        let mut follower = Follower::open(&path, &opts).unwrap();
+        assert_eq!(shown(&follower), ["one", "tw"]);
+
+        append(&path, "o\nthr");
+        assert!(follower.poll(&opts).unwrap());
+        assert_eq!(shown(&follower), ["one", "two", "thr"]);
+    }
+
+    #[test]
+    fn starts_over_when_truncated() {
+        let opts = PreviewOptions::for_tests();
+        let dir = temp_dir_with(&[("app.log", b"one\ntwo\n")]);
+        let path = dir.path().join("app.log");
+        let mut follower = Follower::open(&path, &opts).unwrap();
+
+        fs::write(&path, "new\n").unwrap();
+        assert!(follower.poll(&opts).unwrap());
+        assert_eq!(
+            shown(&follower),
+            ["one", "two", "── file truncated ──", "new"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn starts_over_when_rotated() {
+        let opts = PreviewOptions::for_tests();
+        let dir = temp_dir_with(&[("app.log", b"old\n")]);
+        let path = dir.path().join("app.log");
+        let mut follower = Follower::open(&path, &opts).unwrap();
+
+        fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        fs::write(&path, "fresh line\n").unwrap();
+        assert!(follower.poll(&opts).unwrap());
+        assert_eq!(
+            shown(&follower),
+            ["old", "── file rotated ──", "fresh line"]
+        );
+    }
+}
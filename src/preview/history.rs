@@ -0,0 +1,121 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span, Text},
+};
+
+use crate::git::CommitInfo;
+
+/// Author names are cut to this many characters in the blame gutter
+const BLAME_AUTHOR_WIDTH: usize = 12;
+
+/// Width of the "<hash> <date> <author>" blame annotation, so uncommitted lines line up
+pub const BLAME_ANNOTATION_WIDTH: usize = 7 + 1 + 10 + 1 + BLAME_AUTHOR_WIDTH;
+
+/// Lists the commits that touched a file like `git log --oneline` with dates and authors
+pub fn history_preview(commits: &[CommitInfo]) -> Text<'static> {
+    if commits.is_empty() {
+        return Text::from(Line::from(Span::styled(
+            "No commits touch this file",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let lines: Vec<Line<'static>> = commits
+        .iter()
+        .map(|commit| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{} ", commit.short_id),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!("{} ", commit_date(commit)),
+                    Style::default().fg(Color::Green),
+                ),
+                Span::styled(
+                    format!("{} ", commit.author),
+                    Style::default().fg(Color::Blue),
+                ),
+                Span::raw(commit.subject.clone()),
+            ])
+        })
+        .collect();
+    Text::from(lines)
+}
+
+/// Prefixes each highlighted line with the commit that last changed it
+pub fn blame_preview(mut text: Text<'static>, blame: &[Option<CommitInfo>]) -> Text<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    for (line, commit) in text.lines.iter_mut().zip(blame) {
+        let annotation = match commit {
+            Some(commit) => vec![
+                Span::styled(
+                    format!("{} ", commit.short_id),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(format!("{} ", commit_date(commit)), dim),
+                Span::styled(
+                    format!(
+                        "{:<BLAME_AUTHOR_WIDTH$} ",
+                        truncate(&commit.author, BLAME_AUTHOR_WIDTH)
+                    ),
+                    Style::default().fg(Color::Blue),
+                ),
+            ],
+            None => vec![Span::styled(
+                format!("{:<BLAME_ANNOTATION_WIDTH$} ", "Not committed"),
+                dim,
+            )],
+        };
+        line.spans.splice(0..0, annotation);
+    }
+    text
+}
+
+fn commit_date(commit: &CommitInfo) -> String {
+    DateTime::from_timestamp(commit.time, 0)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::plain_lines;
+
+    fn commit(author: &str) -> CommitInfo {
+        CommitInfo {
+            short_id: "abc1234".to_string(),
+            author: author.to_string(),
+            // Midday, so the date is the same in every time zone
+            time: 1_700_000_000 - 1_700_000_000 % 86400 + 43200,
+            subject: "Fix the thing".to_string(),
+        }
+    }
+
+    #[test]
+    fn blame_annotations_line_up() {
+        let text = Text::from(vec![Line::from("fn a() {}"), Line::from("fn b() {}")]);
+        let blame = [Some(commit("Someone With A Long Name")), None];
+        let lines = plain_lines(&blame_preview(text, &blame).lines);
+
+        assert_eq!(lines[0], "abc1234 2023-11-14 Someone With fn a() {}");
+        assert!(lines[1].starts_with("Not committed "));
+        for line in &lines {
+            assert_eq!(line.chars().count(), BLAME_ANNOTATION_WIDTH + 1 + 9);
+        }
+    }
+
+    #[test]
+    fn history_lists_one_commit_per_line() {
+        let lines = plain_lines(&history_preview(&[commit("Someone")]).lines);
+        assert_eq!(lines, ["abc1234 2023-11-14 Someone Fix the thing"]);
+        let empty = plain_lines(&history_preview(&[]).lines);
+        assert_eq!(empty, ["No commits touch this file"]);
+    }
+}
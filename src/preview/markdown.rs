@@ -0,0 +1,511 @@
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::path::{Path, PathBuf};
+use unicode_width::UnicodeWidthStr;
+
+use crate::highlight::highlight_contents;
+use crate::language::find_syntax_by_name;
+use crate::preview::{MAX_PREVIEW_LINES, PreviewOptions};
+
+/// Extensions of files rendered as Markdown
+const MARKDOWN_EXTENSIONS: [&str; 4] = ["md", "markdown", "mdown", "mkd"];
+
+/// Width of the line drawn for a thematic break (`---`)
+const RULE_WIDTH: usize = 40;
+
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        MARKDOWN_EXTENSIONS
+            .iter()
+            .any(|m| ext.eq_ignore_ascii_case(m))
+    })
+}
+
+/// Renders Markdown for reading: styled headings and emphasis, bulleted and numbered
+/// lists, aligned tables, links followed by their URL and fenced code highlighted like
+/// a file in that language
+pub fn markdown_preview(content: &str, opts: &PreviewOptions) -> Text<'static> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = MarkdownRenderer {
+        opts,
+        lines: Vec::new(),
+        current: Vec::new(),
+        styles: Vec::new(),
+        lists: Vec::new(),
+        bullet: None,
+        quote_depth: 0,
+        code: None,
+        links: Vec::new(),
+        table: None,
+    };
+    for event in Parser::new_ext(content, options) {
+        if renderer.lines.len() >= MAX_PREVIEW_LINES {
+            break;
+        }
+        renderer.event(event);
+    }
+    renderer.flush();
+
+    let mut lines = renderer.lines;
+    lines.truncate(MAX_PREVIEW_LINES);
+    Text::from(lines)
+}
+
+struct ListLevel {
+    /// Number of the next item of an ordered list
+    next: Option<u64>,
+    /// Width of the current item's bullet, which its continuation lines are indented by
+    width: usize,
+}
+
+struct TableBuilder {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+    header_rows: usize,
+}
+
+struct MarkdownRenderer<'o> {
+    opts: &'o PreviewOptions,
+    lines: Vec<Line<'static>>,
+    current: Vec<Span<'static>>,
+    /// Styles of the inline elements being read, innermost last
+    styles: Vec<Style>,
+    lists: Vec<ListLevel>,
+    /// Bullet waiting to be written at the start of an item's first line
+    bullet: Option<String>,
+    quote_depth: usize,
+    /// Language and text so far of the code block being read
+    code: Option<(String, String)>,
+    /// URL and text so far of the links being read
+    links: Vec<(String, String)>,
+    table: Option<TableBuilder>,
+}
+
+impl MarkdownRenderer<'_> {
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text, self.style()),
+            Event::Code(code) => self.text(&code, self.style().fg(Color::Yellow)),
+            Event::InlineHtml(html) | Event::InlineMath(html) | Event::DisplayMath(html) => {
+                self.text(&html, dim())
+            }
+            Event::Html(html) => {
+                for line in html.split_inclusive('\n') {
+                    self.text(line.trim_end_matches('\n'), dim());
+                    if line.ends_with('\n') {
+                        self.flush();
+                    }
+                }
+            }
+            Event::FootnoteReference(name) => self.text(&format!("[^{name}]"), dim()),
+            Event::SoftBreak => self.text(" ", self.style()),
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.gap();
+                self.text(&"─".repeat(RULE_WIDTH), dim());
+                self.flush();
+            }
+            Event::TaskListMarker(done) => {
+                self.text(if done { "[x] " } else { "[ ] " }, self.style())
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock => self.gap(),
+            Tag::Heading { level, .. } => {
+                self.gap();
+                self.styles.push(heading_style(level));
+            }
+            Tag::BlockQuote(_) => {
+                self.gap();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.gap();
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((lang, String::new()));
+            }
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.gap();
+                } else {
+                    self.flush();
+                }
+                self.lists.push(ListLevel {
+                    next: start,
+                    width: 0,
+                });
+            }
+            Tag::Item => {
+                self.flush();
+                if let Some(level) = self.lists.last_mut() {
+                    let bullet = match &mut level.next {
+                        Some(n) => {
+                            *n += 1;
+                            format!("{}. ", *n - 1)
+                        }
+                        None => "• ".to_string(),
+                    };
+                    level.width = bullet.width();
+                    self.bullet = Some(bullet);
+                }
+            }
+            Tag::Table(alignments) => {
+                self.gap();
+                self.table = Some(TableBuilder {
+                    alignments,
+                    rows: Vec::new(),
+                    header_rows: 0,
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(String::new());
+                }
+            }
+            Tag::Emphasis => self
+                .styles
+                .push(Style::default().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self
+                .styles
+                .push(Style::default().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self
+                .styles
+                .push(Style::default().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { dest_url, .. } => {
+                self.styles.push(
+                    Style::default()
+                        .fg(Color::Blue)
+                        .add_modifier(Modifier::UNDERLINED),
+                );
+                self.links.push((dest_url.to_string(), String::new()));
+            }
+            Tag::Image { dest_url, .. } => {
+                self.text("[image: ", dim());
+                self.styles.push(dim());
+                self.links.push((dest_url.to_string(), String::new()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.flush(),
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.flush();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => {
+                if let Some((lang, code)) = self.code.take() {
+                    self.code_block(&lang, &code);
+                }
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+            }
+            TagEnd::Item => self.flush(),
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.table_block(table);
+                }
+            }
+            TagEnd::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.header_rows = table.rows.len();
+                }
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                if let Some((url, text)) = self.links.pop()
+                    && !url.is_empty()
+                    && url != text
+                {
+                    self.text(&format!(" ({url})"), dim());
+                }
+            }
+            TagEnd::Image => {
+                self.styles.pop();
+                self.text("]", dim());
+                if let Some((url, _)) = self.links.pop()
+                    && !url.is_empty()
+                {
+                    self.text(&format!(" ({url})"), dim());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn style(&self) -> Style {
+        self.styles
+            .iter()
+            .fold(Style::default(), |style, inner| style.patch(*inner))
+    }
+
+    /// Adds inline text, to the table cell or code block being read if there is one
+    fn text(&mut self, text: &str, style: Style) {
+        if let Some((_, code)) = &mut self.code {
+            code.push_str(text);
+            return;
+        }
+        if let Some(cell) = self
+            .table
+            .as_mut()
+            .and_then(|table| table.rows.last_mut())
+            .and_then(|row| row.last_mut())
+        {
+            cell.push_str(text);
+            return;
+        }
+        if let Some((_, link_text)) = self.links.last_mut() {
+            link_text.push_str(text);
+        }
+        self.start_line();
+        self.current.push(Span::styled(text.to_string(), style));
+    }
+
+    /// Writes the block quote bars and list indentation or bullet a new line begins with
+    fn start_line(&mut self) {
+        if !self.current.is_empty() {
+            return;
+        }
+        for _ in 0..self.quote_depth {
+            self.current.push(Span::styled("│ ", dim()));
+        }
+        let Some((last, outer)) = self.lists.split_last() else {
+            return;
+        };
+        let indent: usize = outer.iter().map(|level| level.width).sum();
+        match self.bullet.take() {
+            Some(bullet) => {
+                self.current.push(Span::raw(" ".repeat(indent)));
+                self.current
+                    .push(Span::styled(bullet, Style::default().fg(Color::Yellow)));
+            }
+            None => self
+                .current
+                .push(Span::raw(" ".repeat(indent + last.width))),
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.current.is_empty() {
+            self.lines
+                .push(Line::from(std::mem::take(&mut self.current)));
+        }
+    }
+
+    /// Ends the current line and leaves a blank one before the next block, except at the
+    /// very top and right after a list bullet
+    fn gap(&mut self) {
+        self.flush();
+        if self.bullet.is_none() && self.lines.last().is_some_and(|line| line.width() > 0) {
+            self.lines.push(Line::default());
+        }
+    }
+
+    /// Highlights a fenced code block as if it were a file in the block's language
+    fn code_block(&mut self, lang: &str, code: &str) {
+        let path = find_syntax_by_name(lang)
+            .and_then(|syntax| syntax.file_extensions.first())
+            .map_or_else(
+                || PathBuf::from("code"),
+                |ext| PathBuf::from(format!("code.{ext}")),
+            );
+        let text = highlight_contents(&path, code, self.opts);
+        for line in text.lines {
+            self.start_line();
+            self.current.extend(line.spans);
+            let spans = std::mem::take(&mut self.current);
+            self.lines.push(Line::from(spans).style(text.style));
+        }
+    }
+
+    fn table_block(&mut self, table: TableBuilder) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.width())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for (i, row) in table.rows.iter().enumerate() {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, &width)| {
+                    let cell = row.get(column).map_or("", String::as_str);
+                    let alignment = table.alignments.get(column).copied();
+                    align(cell, width, alignment.unwrap_or(Alignment::None))
+                })
+                .collect();
+            let style = if i < table.header_rows {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            self.start_line();
+            self.current.push(Span::styled(cells.join(" │ "), style));
+            self.flush();
+
+            if i + 1 == table.header_rows {
+                let rule: Vec<String> = widths.iter().map(|&width| "─".repeat(width)).collect();
+                self.start_line();
+                self.current.push(Span::styled(rule.join("─┼─"), dim()));
+                self.flush();
+            }
+        }
+    }
+}
+
+fn heading_style(level: HeadingLevel) -> Style {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    match level {
+        HeadingLevel::H1 => bold.fg(Color::Magenta).add_modifier(Modifier::UNDERLINED),
+        HeadingLevel::H2 => bold.fg(Color::Cyan),
+        HeadingLevel::H3 => bold.fg(Color::Blue),
+        _ => bold,
+    }
+}
+
+/// Pads `cell` to `width` display columns according to its column's alignment
+fn align(cell: &str, width: usize, alignment: Alignment) -> String {
+    let padding = width.saturating_sub(cell.width());
+    match alignment {
+        Alignment::Right => format!("{}{cell}", " ".repeat(padding)),
+        Alignment::Center => format!(
+            "{}{cell}{}",
+            " ".repeat(padding / 2),
+            " ".repeat(padding - padding / 2)
+        ),
+        Alignment::Left | Alignment::None => format!("{cell}{}", " ".repeat(padding)),
+    }
+}
+
+fn dim() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::plain_lines;
+
+    fn rendered(markdown: &str) -> Vec<String> {
+        plain_lines(&markdown_preview(markdown, &PreviewOptions::for_tests()).lines)
+    }
+
+    #[test]
+    fn recognises_markdown_extensions() {
+        assert!(is_markdown(Path::new("README.md")));
+        assert!(is_markdown(Path::new("notes.MARKDOWN")));
+        assert!(!is_markdown(Path::new("md")));
+        assert!(!is_markdown(Path::new("main.rs")));
+    }
+
+    #[test]
+    fn blocks_are_separated_by_one_blank_line() {
+        let lines = rendered("# Title\n\nSome *text*\nwrapped.\n\n---\n\n> quoted\n");
+        assert_eq!(
+            lines,
+            [
+                "Title",
+                "",
+                "Some text wrapped.",
+                "",
+                &"─".repeat(RULE_WIDTH),
+                "",
+                "│ quoted",
+            ]
+        );
+    }
+
+    #[test]
+    fn headings_and_emphasis_are_styled() {
+        let text = markdown_preview("# Title\n\n**bold** `code`\n", &PreviewOptions::for_tests());
+        assert_eq!(
+            text.lines[0].spans[0].style,
+            heading_style(HeadingLevel::H1)
+        );
+        let spans = &text.lines[2].spans;
+        assert!(spans[0].style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(spans[2].style.fg, Some(Color::Yellow));
+    }
+
+    #[test]
+    fn lists_are_bulleted_numbered_and_nested() {
+        let lines = rendered("1. one\n2. two\n   - nested\n     more\n- [x] done\n");
+        assert_eq!(
+            lines,
+            ["1. one", "2. two", "   • nested more", "", "• [x] done",]
+        );
+    }
+
+    #[test]
+    fn links_are_followed_by_their_url() {
+        let lines = rendered("[docs](https://example.com) and <https://example.com>\n");
+        assert_eq!(
+            lines,
+            ["docs (https://example.com) and https://example.com"]
+        );
+        let lines = rendered("![logo](logo.png)\n");
+        assert_eq!(lines, ["[image: logo] (logo.png)"]);
+    }
+
+    #[test]
+    fn tables_are_aligned() {
+        let lines = rendered("| a | long header |\n|--:|:---:|\n| 1 | x |\n| 100 | yy |\n");
+        assert_eq!(
+            lines,
+            [
+                "  a │ long header",
+                "────┼────────────",
+                "  1 │      x     ",
+                "100 │     yy     ",
+            ]
+        );
+    }
+
+    #[test]
+    fn fenced_code_is_highlighted_as_its_language() {
+        let text = markdown_preview("```rust\nfn main() {}\n```\n", &PreviewOptions::for_tests());
+        assert_eq!(plain_lines(&text.lines), ["fn main() {}"]);
+        // Highlighted as Rust, so the keyword is colored differently from the name
+        let spans = &text.lines[0].spans;
+        assert!(spans.len() > 1);
+        assert_ne!(spans[0].style.fg, spans.last().unwrap().style.fg);
+    }
+}
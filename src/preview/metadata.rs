@@ -0,0 +1,253 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::filesystem::human_size;
+use crate::sanitize::sanitize;
+
+/// What the header above a preview shows about the selected path
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub size: u64,
+    pub permissions: String,
+    pub owner: Option<String>,
+    pub modified: Option<SystemTime>,
+    /// Where a symlink points, and whether that target exists
+    pub symlink: Option<(PathBuf, bool)>,
+    /// Number of lines, if the preview read the file as text
+    pub lines: Option<usize>,
+    /// The line count only covers the part of the file that was read
+    pub lines_truncated: bool,
+    /// Syntax the file was highlighted as
+    pub language: Option<String>,
+    pub encoding: Option<&'static str>,
+}
+
+impl FileInfo {
+    /// Gathers everything that comes from the filesystem, leaving the text details to be
+    /// filled in by whichever previewer reads the file
+    pub fn read(path: &Path) -> Option<Self> {
+        let link_meta = std::fs::symlink_metadata(path).ok()?;
+        let symlink = if link_meta.file_type().is_symlink() {
+            std::fs::read_link(path)
+                .ok()
+                .map(|target| (target, path.exists()))
+        } else {
+            None
+        };
+        // Describe what the link points at, or the link itself if it is broken
+        let meta = std::fs::metadata(path).unwrap_or(link_meta);
+
+        Some(FileInfo {
+            size: meta.len(),
+            permissions: permissions(&meta),
+            owner: owner(&meta),
+            modified: meta.modified().ok(),
+            symlink,
+            lines: None,
+            lines_truncated: false,
+            language: None,
+            encoding: None,
+        })
+    }
+
+    pub fn header_lines(&self) -> Vec<Line<'static>> {
+        let label = Style::default().fg(Color::DarkGray);
+        let field = |name: &str, value: String| {
+            vec![
+                Span::styled(format!("{name} "), label),
+                Span::raw(value),
+                Span::raw("  "),
+            ]
+        };
+
+        let mut first = field("size", human_size(self.size));
+        first.extend(field("mode", self.permissions.clone()));
+        if let Some(owner) = &self.owner {
+            first.extend(field("owner", owner.clone()));
+        }
+
+        let mut second = Vec::new();
+        if let Some(modified) = self.modified {
+            let absolute: DateTime<Local> = modified.into();
+            second.extend(field(
+                "modified",
+                format!(
+                    "{} ({})",
+                    relative_time(modified),
+                    absolute.format("%Y-%m-%d %H:%M:%S")
+                ),
+            ));
+        }
+
+        let mut third = Vec::new();
+        if let Some(lines) = self.lines {
+            let plus = if self.lines_truncated { "+" } else { "" };
+            third.extend(field("lines", format!("{lines}{plus}")));
+        }
+        if let Some(language) = &self.language {
+            third.extend(field("language", language.clone()));
+        }
+        if let Some(encoding) = self.encoding {
+            third.extend(field("encoding", encoding.to_string()));
+        }
+
+        let mut lines: Vec<Line<'static>> = [first, second, third]
+            .into_iter()
+            .filter(|spans| !spans.is_empty())
+            .map(Line::from)
+            .collect();
+
+        if let Some((target, exists)) = &self.symlink {
+            let target = sanitize(&target.to_string_lossy()).into_owned();
+            let mut spans = vec![Span::styled("link → ", label), Span::raw(target)];
+            if !exists {
+                spans.push(Span::styled("  (broken)", Style::default().fg(Color::Red)));
+            }
+            lines.push(Line::from(spans));
+        }
+        lines
+    }
+}
+
+/// "3 minutes ago", "2 days ago", ... or "in the future" for clock skew
+fn relative_time(time: SystemTime) -> String {
+    let Ok(elapsed) = SystemTime::now().duration_since(time) else {
+        return "in the future".to_string();
+    };
+    let secs = elapsed.as_secs();
+    let (amount, unit) = match secs {
+        0..60 => return "just now".to_string(),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        86400..2_592_000 => (secs / 86400, "day"),
+        2_592_000..31_536_000 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
+
+#[cfg(unix)]
+fn permissions(meta: &Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = meta.permissions().mode();
+    let kind = if meta.is_dir() { 'd' } else { '-' };
+    let mut out = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    out
+}
+
+#[cfg(not(unix))]
+fn permissions(meta: &Metadata) -> String {
+    if meta.permissions().readonly() {
+        "read-only".to_string()
+    } else {
+        "read-write".to_string()
+    }
+}
+
+#[cfg(unix)]
+fn owner(meta: &Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let user = uzers::get_user_by_uid(meta.uid())
+        .map(|u| u.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| meta.uid().to_string());
+    let group = uzers::get_group_by_gid(meta.gid())
+        .map(|g| g.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| meta.gid().to_string());
+    Some(format!("{user}:{group}"))
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &Metadata) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+    use std::time::Duration;
+
+    #[test]
+    fn times_are_relative_to_now() {
+        let ago = |secs| relative_time(SystemTime::now() - Duration::from_secs(secs));
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(3 * 3600), "3 hours ago");
+        assert_eq!(ago(2 * 86400), "2 days ago");
+        assert_eq!(ago(400 * 86400), "1 year ago");
+        assert_eq!(
+            relative_time(SystemTime::now() + Duration::from_secs(600)),
+            "in the future"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_size_and_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir_with(&[("script.sh", b"echo hi\n")]);
+        let path = dir.path().join("script.sh");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        let info = FileInfo::read(&path).unwrap();
+        assert_eq!(info.size, 8);
+        assert_eq!(info.permissions, "-rwxr-x---");
+        assert!(info.symlink.is_none());
+        assert_eq!(
+            FileInfo::read(dir.path())
+                .unwrap()
+                .permissions
+                .chars()
+                .next(),
+            Some('d')
+        );
+        assert!(FileInfo::read(&dir.path().join("missing")).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlinks_are_flagged() {
+        let dir = temp_dir_with(&[]);
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink("gone.txt", &link).unwrap();
+
+        let info = FileInfo::read(&link).unwrap();
+        assert_eq!(info.symlink, Some((PathBuf::from("gone.txt"), false)));
+        let header = plain_lines(&info.header_lines());
+        assert_eq!(header.last().unwrap(), "link → gone.txt  (broken)");
+    }
+
+    #[test]
+    fn text_details_go_on_their_own_line() {
+        let dir = temp_dir_with(&[("a.txt", b"x")]);
+        let mut info = FileInfo::read(&dir.path().join("a.txt")).unwrap();
+        info.lines = Some(1000);
+        info.lines_truncated = true;
+        info.language = Some("Plain Text".to_string());
+        info.encoding = Some("UTF-8");
+
+        let header = plain_lines(&info.header_lines());
+        assert!(header[0].starts_with("size 1B  mode "));
+        assert!(header[1].starts_with("modified just now ("));
+        assert_eq!(
+            header[2],
+            "lines 1000+  language Plain Text  encoding UTF-8  "
+        );
+    }
+}
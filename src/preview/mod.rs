@@ -0,0 +1,652 @@
+use encoding_rs::Encoding;
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span, Text},
+};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::color::ColorDepth;
+use crate::git::{blame_lines, diff_against_head, file_history};
+use crate::highlight::{highlight_contents, highlight_lines};
+use crate::language::{SyntaxMap, detect_syntax};
+use crate::preview::archive::{ArchiveKind, ArchiveMember, Compression};
+use crate::preview::cache::CacheKey;
+use crate::preview::external::{CommandError, run_preview_command, uses_line, uses_query};
+use crate::preview::metadata::FileInfo;
+use crate::preview::structured::{DataFormat, Structured, TableData};
+
+pub mod ansi;
+pub mod archive;
+pub mod binary;
+pub mod cache;
+pub mod compare;
+pub mod diff;
+pub mod encoding;
+pub mod external;
+pub mod follow;
+pub mod history;
+pub mod markdown;
+pub mod metadata;
+pub mod prefetch;
+pub mod search;
+pub mod structured;
+pub mod tree;
+
+/// Only the top of a file is read into the preview
+pub const MAX_PREVIEW_LINES: usize = 50;
+
+/// Files are read up to this many bytes, more than enough for `MAX_PREVIEW_LINES`
+pub const MAX_PREVIEW_BYTES: u64 = 1024 * 1024;
+
+/// JSON, YAML and TOML documents only parse whole, so they are read up to this many
+/// bytes for their structured preview
+const MAX_STRUCTURED_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Extensions whose ANSI color codes are rendered instead of shown as escapes
+const ANSI_EXTENSIONS: [&str; 2] = ["log", "ansi"];
+
+/// How many commits the history view lists
+const MAX_HISTORY_COMMITS: usize = 50;
+
+/// Views of a file's git history that replace the regular preview
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitView {
+    /// The commits that touched the file
+    History,
+    /// The file annotated with the commit that last changed each line
+    Blame,
+}
+
+/// Settings that change how previews are rendered, resolved from the CLI and config file
+#[derive(Debug, Clone)]
+pub struct PreviewOptions {
+    pub theme: String,
+    pub color_depth: ColorDepth,
+    /// Show a line number gutter next to file contents
+    pub line_numbers: bool,
+    /// Soft-wrap long lines instead of cutting them off at the pane edge
+    pub wrap: bool,
+    /// Columns a tab character expands to
+    pub tab_width: usize,
+    /// Render ANSI colors in `.log`/`.ansi` files rather than showing the raw escapes
+    pub interpret_ansi: bool,
+    /// Glob overrides for which syntax a file is highlighted with
+    pub syntax_map: Arc<SyntaxMap>,
+    /// Show uncommitted changes instead of the contents for modified files in a git repo
+    pub show_diff: bool,
+    /// Show the file metadata header above the preview
+    pub show_metadata: bool,
+    /// Git history view shown in place of the contents, if any
+    pub git_view: Option<GitView>,
+    /// Pretty-print JSON, lay out CSV/TSV as tables and render Markdown rather than
+    /// showing their source
+    pub structured: bool,
+    /// Fold JSON objects and arrays nested this deep, `None` to expand everything
+    pub collapse_depth: Option<usize>,
+    /// Preview every other file as a diff against this one
+    pub compare_with: Option<PathBuf>,
+    /// Two marked files, each previewed as a diff against the other
+    pub compare_pair: Option<[PathBuf; 2]>,
+    /// Lay comparisons out side by side rather than as a unified diff
+    pub side_by_side: bool,
+    /// `--preview` command used for every selection instead of the built-in previews
+    pub preview_command: Option<String>,
+    /// Preview commands for particular file extensions, from the config file
+    pub preview_commands: Arc<BTreeMap<String, String>>,
+    /// How long a preview command may run before it is killed
+    pub preview_timeout: Duration,
+}
+
+impl PreviewOptions {
+    /// The external command that previews `path`, if any. `--preview` wins over the
+    /// per-extension commands; without either the built-in previews are used.
+    pub fn command_for(&self, path: &Path) -> Option<&str> {
+        if let Some(command) = &self.preview_command {
+            return Some(command);
+        }
+        if path.is_dir() {
+            return None;
+        }
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.preview_commands.get(&ext).map(String::as_str)
+    }
+
+    /// Whether previews of `path` change with the query and so must be cached per query
+    pub fn query_dependent(&self, path: &Path) -> bool {
+        self.command_for(path).is_some_and(uses_query)
+    }
+
+    /// Whether previews of `path` change with the line in focus and so must be cached per line
+    pub fn line_dependent(&self, path: &Path) -> bool {
+        self.command_for(path).is_some_and(uses_line)
+    }
+
+    /// The file `path` is previewed as a diff against: the other one of a marked pair,
+    /// or else the Ctrl+X base unless that is `path` itself
+    pub fn compare_base(&self, path: &Path) -> Option<&Path> {
+        if let Some([first, second]) = &self.compare_pair {
+            if path == first {
+                return Some(second);
+            }
+            if path == second {
+                return Some(first);
+            }
+        }
+        self.compare_with.as_deref().filter(|base| *base != path)
+    }
+}
+
+/// A rendered preview, ready to be laid out into the preview pane
+pub struct Preview {
+    pub text: Text<'static>,
+    /// Whether each line is a line of the file, so it gets a line number in the gutter
+    pub numbered: bool,
+    /// Text encoding the file was decoded from, shown in the title
+    pub encoding: Option<&'static Encoding>,
+    /// Details for the optional metadata header
+    pub info: Option<FileInfo>,
+    /// Names the kind of view this is in the title, for previews other than the contents
+    pub label: Option<&'static str>,
+    /// Rows to draw as a table instead of `text`
+    pub table: Option<TableData>,
+    /// A problem with the file worth pointing out above the preview, e.g. a parse error
+    pub notice: Option<String>,
+    /// Set when the lines are the lines of the file on disk, to how many characters of
+    /// annotation, such as blame, come before the file's text on each
+    pub source_offset: Option<usize>,
+}
+
+impl Preview {
+    /// A preview of generated content, such as a listing, that shouldn't be numbered
+    pub fn listing(text: Text<'static>) -> Self {
+        Preview {
+            text,
+            numbered: false,
+            encoding: None,
+            info: None,
+            label: None,
+            table: None,
+            notice: None,
+            source_offset: None,
+        }
+    }
+
+    /// A preview laid out as a table
+    pub fn table(table: TableData) -> Self {
+        Preview {
+            table: Some(table),
+            ..Preview::listing(Text::default())
+        }
+    }
+
+    pub fn with_label(mut self, label: &'static str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// What a search in the preview of `path` looks through: the rows of a table laid out
+    /// as they are drawn, every line of the file with its tabs expanded when the preview
+    /// shows the file's text, including lines not rendered yet, or else the text as shown
+    pub fn search_lines(&self, path: &Path, tab_width: usize) -> Cow<'_, [Line<'static>]> {
+        if let Some(table) = &self.table {
+            return Cow::Owned(table.row_lines());
+        }
+        if self.source_offset.is_some()
+            && let Some((content, _)) = read_text(path)
+        {
+            return Cow::Owned(
+                content
+                    .lines()
+                    .map(|line| Line::from(expand_tabs(line, tab_width)))
+                    .collect(),
+            );
+        }
+        Cow::Borrowed(&self.text.lines)
+    }
+
+    /// Rough size of the rendered text in memory, used to keep the cache within budget
+    pub fn approx_bytes(&self) -> usize {
+        let table_bytes = self.table.as_ref().map_or(0, |table| {
+            std::iter::once(&table.header)
+                .chain(&table.rows)
+                .flatten()
+                .map(|cell| std::mem::size_of::<String>() + cell.len())
+                .sum::<usize>()
+        });
+        let notice_bytes = self.notice.as_ref().map_or(0, String::len);
+        let text_bytes: usize = self
+            .text
+            .lines
+            .iter()
+            .map(|line| {
+                std::mem::size_of::<Line>()
+                    + line
+                        .spans
+                        .iter()
+                        .map(|span| std::mem::size_of::<Span>() + span.content.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        text_bytes + table_bytes + notice_bytes
+    }
+}
+
+#[cfg(test)]
+impl PreviewOptions {
+    /// The options an empty config file gives, for tests
+    pub fn for_tests() -> Self {
+        PreviewOptions {
+            theme: crate::highlight::DEFAULT_THEME.to_string(),
+            color_depth: ColorDepth::TrueColor,
+            line_numbers: true,
+            wrap: false,
+            tab_width: 4,
+            interpret_ansi: true,
+            syntax_map: Arc::default(),
+            preview_command: None,
+            preview_commands: Arc::default(),
+            preview_timeout: Duration::from_millis(3000),
+            show_metadata: false,
+            show_diff: true,
+            git_view: None,
+            structured: true,
+            collapse_depth: None,
+            compare_with: None,
+            compare_pair: None,
+            side_by_side: false,
+        }
+    }
+}
+
+/// Builds the preview for whatever is at `key.path`, picking a renderer based on what it
+/// is. Returns `None` only if `cancelled` reported that the preview is no longer wanted
+/// before a preview command finished.
+pub fn render_preview(
+    key: &CacheKey,
+    opts: &PreviewOptions,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<Preview> {
+    let mut preview = render_contents(key, opts, cancelled)?;
+    if preview.info.is_none() {
+        preview.info = FileInfo::read(&key.path);
+    }
+    Some(preview)
+}
+
+fn render_contents(
+    key: &CacheKey,
+    opts: &PreviewOptions,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<Preview> {
+    let path = key.path.as_path();
+    if let Some(member) = ArchiveMember::from_path(path) {
+        let bytes = member.read(MAX_PREVIEW_BYTES);
+        return Some(inner_text_preview(bytes, path, "archive member", opts));
+    }
+
+    if let Some(command) = opts.command_for(path) {
+        return match run_preview_command(command, key, opts.preview_timeout, cancelled) {
+            Ok(output) => Some(Preview::listing(ansi::ansi_contents(
+                &String::from_utf8_lossy(&output),
+                opts,
+                MAX_PREVIEW_LINES,
+            ))),
+            Err(CommandError::Cancelled) => None,
+            Err(CommandError::Failed(message)) => Some(error_preview(&message)),
+        };
+    }
+
+    if let Some(base) = opts.compare_base(path)
+        && path.is_file()
+    {
+        return Some(compare::compare_preview(base, path, opts, key.width));
+    }
+
+    if path.is_dir() {
+        return Some(Preview::listing(tree::tree_preview(
+            path,
+            tree::DEFAULT_TREE_DEPTH,
+        )));
+    }
+
+    if opts.git_view == Some(GitView::History)
+        && let Some(commits) = file_history(path, MAX_HISTORY_COMMITS)
+    {
+        return Some(Preview::listing(history::history_preview(&commits)).with_label("history"));
+    }
+
+    if let Some(kind) = ArchiveKind::from_path(path) {
+        return Some(match archive::list_archive(path, kind) {
+            Ok(listing) => {
+                Preview::listing(archive::archive_preview(&listing)).with_label("archive")
+            }
+            Err(e) => error_preview(&format!("Can't read archive: {e}")),
+        });
+    }
+
+    if let Some(compression) = Compression::from_path(path) {
+        let bytes = archive::read_compressed(path, compression, MAX_PREVIEW_BYTES);
+        return Some(inner_text_preview(
+            bytes,
+            &path.with_extension(""),
+            "decompressed",
+            opts,
+        ));
+    }
+
+    if opts.git_view.is_none()
+        && opts.show_diff
+        && let Some(diff) = diff_against_head(path)
+    {
+        return Some(
+            Preview::listing(diff::diff_preview(&diff, opts.tab_width)).with_label("diff vs HEAD"),
+        );
+    }
+
+    let Some((content, encoding)) = read_text(path) else {
+        return Some(match binary::object_summary(path) {
+            Some(summary) => Preview::listing(summary).with_label("binary"),
+            None => no_preview(),
+        });
+    };
+    let len = std::fs::metadata(path).map_or(0, |meta| meta.len());
+    let truncated = len > MAX_PREVIEW_BYTES;
+
+    let mut notice = None;
+    if opts.structured
+        && opts.git_view.is_none()
+        && let Some(format) = DataFormat::from_path(path)
+        && let Some(document) = if !truncated || format.allows_partial() {
+            Some(Cow::Borrowed(content.as_str()))
+        } else if len <= MAX_STRUCTURED_BYTES {
+            read_text_up_to(path, MAX_STRUCTURED_BYTES).map(|(whole, _)| Cow::Owned(whole))
+        } else {
+            None
+        }
+    {
+        match structured::parse(format, &document, opts.collapse_depth) {
+            Ok(Structured::Text(text)) => {
+                return Some(Preview {
+                    encoding: Some(encoding),
+                    ..Preview::listing(text).with_label("pretty")
+                });
+            }
+            Ok(Structured::Table(table)) => {
+                return Some(Preview {
+                    encoding: Some(encoding),
+                    ..Preview::table(table).with_label("table")
+                });
+            }
+            Ok(Structured::Reformatted(pretty)) => {
+                let text = highlight_contents(path, &pretty, opts);
+                return Some(Preview {
+                    encoding: Some(encoding),
+                    ..Preview::listing(text).with_label("pretty")
+                });
+            }
+            Err(error) => notice = Some(error.to_string()),
+        }
+    }
+
+    if opts.structured && opts.git_view.is_none() && markdown::is_markdown(path) {
+        return Some(Preview {
+            encoding: Some(encoding),
+            ..Preview::listing(markdown::markdown_preview(&content, opts)).with_label("rendered")
+        });
+    }
+
+    let (text, language) = style_contents(path, &content, opts, key.lines);
+    let blame = match opts.git_view {
+        Some(GitView::Blame) => blame_lines(path, content.lines().count()),
+        _ => None,
+    };
+
+    let info = FileInfo::read(path).map(|mut info| {
+        info.lines = Some(content.lines().count());
+        info.lines_truncated = truncated;
+        info.language = Some(language);
+        info.encoding = Some(encoding.name());
+        info
+    });
+
+    let (text, label, source_offset) = match blame {
+        Some(blame) => (
+            history::blame_preview(text, &blame),
+            Some("blame"),
+            history::BLAME_ANNOTATION_WIDTH + 1,
+        ),
+        None => (text, None, 0),
+    };
+
+    Some(Preview {
+        text,
+        numbered: true,
+        encoding: Some(encoding),
+        info,
+        label,
+        table: None,
+        notice,
+        source_offset: Some(source_offset),
+    })
+}
+
+/// Colors the first `max_lines` lines of the text of `path`, interpreting ANSI escapes in
+/// log-like files and highlighting the syntax of anything else. Also returns the name of
+/// the language.
+pub fn style_contents(
+    path: &Path,
+    content: &str,
+    opts: &PreviewOptions,
+    max_lines: usize,
+) -> (Text<'static>, String) {
+    let is_ansi = path
+        .extension()
+        .is_some_and(|ext| ANSI_EXTENSIONS.iter().any(|a| ext.eq_ignore_ascii_case(a)));
+    if is_ansi && opts.interpret_ansi {
+        (
+            ansi::ansi_contents(content, opts, max_lines),
+            "ANSI".to_string(),
+        )
+    } else {
+        let language = detect_syntax(path, content, &opts.syntax_map).name.clone();
+        (highlight_lines(path, content, opts, max_lines), language)
+    }
+}
+
+/// Previews text unpacked from a compressed file or an archive, highlighted according to
+/// the name it had before it was packed
+fn inner_text_preview(
+    bytes: std::io::Result<Vec<u8>>,
+    inner_path: &Path,
+    label: &'static str,
+    opts: &PreviewOptions,
+) -> Preview {
+    let bytes = match bytes {
+        Ok(bytes) => bytes,
+        Err(e) => return error_preview(&format!("Can't unpack: {e}")),
+    };
+    let Some((content, encoding)) = encoding::decode(&bytes) else {
+        return no_preview();
+    };
+    Preview {
+        text: highlight_contents(inner_path, &content, opts),
+        numbered: true,
+        encoding: Some(encoding),
+        ..Preview::listing(Text::default()).with_label(label)
+    }
+}
+
+/// Lines `range` of the file at `path`, counted from 0, as they are written rather than
+/// as a preview renders them. `None` if the file isn't text or has no such lines.
+pub fn source_lines(path: &Path, range: RangeInclusive<usize>) -> Option<String> {
+    let (content, _) = read_text(path)?;
+    let lines: Vec<&str> = content
+        .lines()
+        .skip(*range.start())
+        .take(range.end() - range.start() + 1)
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Reads the start of a file and decodes it to UTF-8, `None` if it isn't text
+fn read_text(path: &Path) -> Option<(String, &'static Encoding)> {
+    read_text_up_to(path, MAX_PREVIEW_BYTES)
+}
+
+/// Same as [`read_text`] but reading up to `limit` bytes
+fn read_text_up_to(path: &Path, limit: u64) -> Option<(String, &'static Encoding)> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(limit)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    encoding::decode(&bytes)
+}
+
+/// Placeholder shown when nothing sensible can be rendered for a path
+pub fn no_preview() -> Preview {
+    Preview::listing(Text::from(Line::from(Span::styled(
+        "No Preview available",
+        Style::default().fg(Color::DarkGray),
+    ))))
+}
+
+/// Shows why a preview couldn't be produced
+pub fn error_preview(message: &str) -> Preview {
+    let lines: Vec<Line<'static>> = message
+        .lines()
+        .map(|line| {
+            Line::from(Span::styled(
+                line.to_string(),
+                Style::default().fg(Color::Red),
+            ))
+        })
+        .collect();
+    Preview::listing(Text::from(lines))
+}
+
+/// Stands in while a preview command is still running in the background
+pub fn pending_preview() -> Preview {
+    Preview::listing(Text::from(Line::from(Span::styled(
+        "Running preview command…",
+        Style::default().fg(Color::DarkGray),
+    ))))
+}
+
+/// Replaces tabs with spaces up to the next multiple of `tab_width`, so that indentation
+/// lines up no matter how the terminal would have rendered a raw tab
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    expand_tabs_from(line, tab_width, &mut 0)
+}
+
+/// Same as [`expand_tabs`] but across the spans of an already styled line
+pub fn expand_tabs_in_line(line: Line<'static>, tab_width: usize) -> Line<'static> {
+    let mut column = 0;
+    let spans: Vec<Span<'static>> = line
+        .spans
+        .into_iter()
+        .map(|span| {
+            Span::styled(
+                expand_tabs_from(&span.content, tab_width, &mut column),
+                span.style,
+            )
+        })
+        .collect();
+    Line::from(spans).style(line.style)
+}
+
+/// Expands tabs in `text`, which starts at display column `column` of its line
+fn expand_tabs_from(text: &str, tab_width: usize, column: &mut usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut expanded = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch == '\t' {
+            let spaces = tab_width - *column % tab_width;
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            *column += spaces;
+        } else {
+            expanded.push(ch);
+            *column += unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::search::{SearchMatch, find_matches};
+    use crate::test_util::temp_dir_with;
+
+    #[test]
+    fn tabs_expand_to_the_next_stop() {
+        assert_eq!(expand_tabs("	x", 4), "    x");
+        assert_eq!(expand_tabs("ab	x", 4), "ab  x");
+        assert_eq!(expand_tabs("abcd	x", 4), "abcd    x");
+        assert_eq!(expand_tabs("漢	x", 4), "漢  x");
+        assert_eq!(expand_tabs("	x", 0), " x");
+    }
+
+    #[test]
+    fn tab_stops_carry_across_spans() {
+        let line = Line::from(vec![Span::raw("ab"), Span::raw("\tx")]);
+        let expanded = expand_tabs_in_line(line, 8);
+        assert_eq!(expanded.spans[1].content, "      x");
+    }
+
+    #[test]
+    fn pretty_prints_json_past_the_text_read_limit() {
+        let items: Vec<String> = (0..60_000)
+            .map(|n| format!(r#"{{"id":{n},"name":"item"}}"#))
+            .collect();
+        let json = format!("[{}]", items.join(","));
+        assert!(json.len() as u64 > MAX_PREVIEW_BYTES);
+        let dir = temp_dir_with(&[("big.json", json.as_bytes())]);
+        let key = CacheKey::new(
+            &dir.path().join("big.json"),
+            "",
+            1,
+            MAX_PREVIEW_LINES,
+            80,
+            24,
+        );
+        let preview = render_preview(&key, &PreviewOptions::for_tests(), &|| false).unwrap();
+
+        assert_eq!(preview.label, Some("pretty"));
+        assert_eq!(preview.notice, None);
+        assert_eq!(preview.text.lines.len(), MAX_PREVIEW_LINES);
+    }
+
+    #[test]
+    fn searches_past_the_rendered_lines() {
+        let content: String = (1..=200)
+            .map(|n| match n {
+                150 => "\tneedle\n".to_string(),
+                n => format!("line {n}\n"),
+            })
+            .collect();
+        let dir = temp_dir_with(&[("long.txt", content.as_bytes())]);
+        let path = dir.path().join("long.txt");
+        let key = CacheKey::new(&path, "", 1, MAX_PREVIEW_LINES, 80, 24);
+        let preview = render_preview(&key, &PreviewOptions::for_tests(), &|| false).unwrap();
+        assert_eq!(preview.text.lines.len(), MAX_PREVIEW_LINES);
+
+        // The tab is expanded as the preview shows it
+        let matches = find_matches(&preview.search_lines(&path, 4), "needle");
+        assert_eq!(
+            matches,
+            [SearchMatch {
+                line: 149,
+                start: 4,
+                end: 10
+            }]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::preview::cache::CacheKey;
+use crate::preview::{Preview, PreviewOptions, render_preview};
+
+/// Nice value of the prefetch thread, so it only gets the CPU time the UI leaves over
+#[cfg(target_os = "linux")]
+const PREFETCH_NICENESS: libc::c_int = 10;
+
+/// Previews worth having ready, in the order they should be rendered
+struct Job {
+    generation: u64,
+    keys: Vec<CacheKey>,
+    opts: PreviewOptions,
+}
+
+/// Renders previews the user is likely to look at next on a background thread, so that
+/// moving through the results hits the cache instead of reading and highlighting files.
+///
+/// Each new request supersedes the previous one: bumping the shared generation makes the
+/// worker abandon whatever it was still working through, and any results it already sent
+/// for an older generation are ignored when collected.
+pub struct Prefetcher {
+    jobs: Sender<Job>,
+    results: Receiver<(u64, CacheKey, Arc<Preview>)>,
+    generation: Arc<AtomicU64>,
+}
+
+impl Prefetcher {
+    /// Starts the background worker, which lives until the prefetcher is dropped
+    pub fn spawn() -> Self {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, results) = mpsc::channel();
+        let generation = Arc::new(AtomicU64::new(0));
+
+        let worker_generation = generation.clone();
+        thread::Builder::new()
+            .name("preview-prefetch".to_string())
+            .spawn(move || {
+                lower_priority();
+                while let Ok(mut job) = job_rx.recv() {
+                    // Skip straight to the newest request if several queued up
+                    while let Ok(newer) = job_rx.try_recv() {
+                        job = newer;
+                    }
+
+                    let superseded = || worker_generation.load(Ordering::Relaxed) != job.generation;
+                    for key in job.keys {
+                        // Gives way to the UI thread between previews
+                        thread::yield_now();
+                        if superseded() {
+                            break;
+                        }
+                        let Some(preview) = render_preview(&key, &job.opts, &superseded) else {
+                            break;
+                        };
+                        if result_tx
+                            .send((job.generation, key, Arc::new(preview)))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn the preview prefetch thread");
+
+        Prefetcher {
+            jobs,
+            results,
+            generation,
+        }
+    }
+
+    /// Replaces any outstanding work with rendering `keys`
+    pub fn request(&self, keys: Vec<CacheKey>, opts: &PreviewOptions) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        if keys.is_empty() {
+            return;
+        }
+        let _ = self.jobs.send(Job {
+            generation,
+            keys,
+            opts: opts.clone(),
+        });
+    }
+
+    /// Stops outstanding work, e.g. when the options it was started with are outdated
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Previews finished since the last call that are still wanted
+    pub fn finished(&self) -> Vec<(CacheKey, Arc<Preview>)> {
+        let current = self.generation.load(Ordering::Relaxed);
+        self.results
+            .try_iter()
+            .filter(|(generation, _, _)| *generation == current)
+            .map(|(_, key, preview)| (key, preview))
+            .collect()
+    }
+}
+
+/// Lowers the priority of the calling thread. On Linux a nice value set with the thread's
+/// id only applies to that thread; elsewhere it would apply to the whole process, so the
+/// worker only yields between previews there.
+#[cfg(target_os = "linux")]
+fn lower_priority() {
+    // SAFETY: gettid and setpriority have no memory safety requirements
+    unsafe {
+        let tid = libc::gettid() as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, tid, PREFETCH_NICENESS);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_priority() {}
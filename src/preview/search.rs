@@ -0,0 +1,184 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::sanitize::sanitize_line;
+
+/// One occurrence of the search pattern, as a range of characters in the sanitized line,
+/// which is the text actually on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Finds every occurrence of `pattern` in the preview. Like vim's smartcase, the search
+/// ignores case unless the pattern contains an uppercase letter.
+pub fn find_matches(lines: &[Line<'_>], pattern: &str) -> Vec<SearchMatch> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    let case_sensitive = pattern.iter().any(|ch| ch.is_uppercase());
+    let same = |a: char, b: char| {
+        if case_sensitive {
+            a == b
+        } else {
+            a == b || a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+
+    let mut matches = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let chars: Vec<char> = sanitize_line(line)
+            .spans
+            .iter()
+            .flat_map(|span| span.content.chars().collect::<Vec<_>>())
+            .collect();
+        let mut start = 0;
+        while start + pattern.len() <= chars.len() {
+            let window = &chars[start..start + pattern.len()];
+            if window.iter().zip(&pattern).all(|(&a, &b)| same(a, b)) {
+                matches.push(SearchMatch {
+                    line: i,
+                    start,
+                    end: start + pattern.len(),
+                });
+                start += pattern.len();
+            } else {
+                start += 1;
+            }
+        }
+    }
+    matches
+}
+
+/// The display columns `found` spans in `line`, which differ from its character range
+/// when wide characters come before or inside it
+pub fn match_columns(line: &Line<'_>, found: SearchMatch) -> (usize, usize) {
+    let mut columns = (0, 0);
+    let chars = sanitize_line(line)
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    for (i, ch) in chars.iter().enumerate().take(found.end) {
+        let width = ch.width().unwrap_or(0);
+        if i < found.start {
+            columns.0 += width;
+        }
+        columns.1 += width;
+    }
+    columns
+}
+
+/// Patches `style` onto the characters of the already sanitized `line` that fall in
+/// any of `ranges`, keeping the syntax colors of everything else
+pub fn highlight_ranges(line: &Line<'static>, ranges: &[(usize, usize, Style)]) -> Line<'static> {
+    if ranges.is_empty() {
+        return line.clone();
+    }
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut column = 0;
+    for span in &line.spans {
+        let mut current = String::new();
+        let mut current_style = None;
+        for ch in span.content.chars() {
+            let style = ranges
+                .iter()
+                .find(|(start, end, _)| (*start..*end).contains(&column))
+                .map_or(span.style, |(_, _, highlight)| span.style.patch(*highlight));
+            if let Some(previous) = current_style
+                && previous != style
+            {
+                spans.push(Span::styled(std::mem::take(&mut current), previous));
+            }
+            current_style = Some(style);
+            current.push(ch);
+            column += 1;
+        }
+        if let Some(style) = current_style {
+            spans.push(Span::styled(current, style));
+        }
+    }
+    let mut highlighted = Line::from(spans).style(line.style);
+    highlighted.alignment = line.alignment;
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::plain_lines;
+    use ratatui::style::Color;
+
+    fn found(line: usize, start: usize, end: usize) -> SearchMatch {
+        SearchMatch { line, start, end }
+    }
+
+    #[test]
+    fn matches_with_smartcase() {
+        let lines = [Line::from("Foo foo FOO"), Line::from("no match")];
+        assert_eq!(
+            find_matches(&lines, "foo"),
+            [found(0, 0, 3), found(0, 4, 7), found(0, 8, 11)]
+        );
+        assert_eq!(find_matches(&lines, "Foo"), [found(0, 0, 3)]);
+        assert!(find_matches(&lines, "").is_empty());
+    }
+
+    #[test]
+    fn matches_dont_overlap_and_span_spans() {
+        let lines = [Line::from(vec![Span::raw("aa"), Span::raw("aa")])];
+        assert_eq!(find_matches(&lines, "aaa"), [found(0, 0, 3)]);
+        assert_eq!(find_matches(&lines, "aa"), [found(0, 0, 2), found(0, 2, 4)]);
+    }
+
+    #[test]
+    fn matches_the_text_as_shown() {
+        // The escape is shown as `^[`, so that is what the ranges are counted in
+        let lines = [Line::from("\x1b[1mbold")];
+        assert_eq!(find_matches(&lines, "bold"), [found(0, 5, 9)]);
+        assert_eq!(find_matches(&lines, "^["), [found(0, 0, 2)]);
+    }
+
+    #[test]
+    fn columns_count_wide_characters_twice() {
+        let line = Line::from("漢字 name");
+        let matches = find_matches(std::slice::from_ref(&line), "name");
+        assert_eq!(matches, [found(0, 3, 7)]);
+        assert_eq!(match_columns(&line, matches[0]), (5, 9));
+        assert_eq!(match_columns(&line, found(0, 1, 2)), (2, 4));
+    }
+
+    #[test]
+    fn highlighting_keeps_the_colors_around_a_match() {
+        let red = Style::default().fg(Color::Red);
+        let line = Line::from(vec![Span::styled("let x", red), Span::raw(" = 1")]);
+        let mark = Style::default().bg(Color::Yellow);
+
+        let highlighted = highlight_ranges(&line, &[(4, 7, mark)]);
+        assert_eq!(
+            plain_lines(std::slice::from_ref(&highlighted)),
+            ["let x = 1"]
+        );
+        let styles: Vec<(&str, Style)> = highlighted
+            .spans
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                ("let ", red),
+                ("x", red.patch(mark)),
+                (" =", Style::default().patch(mark)),
+                (" 1", Style::default()),
+            ]
+        );
+        assert_eq!(highlight_ranges(&line, &[]), line);
+    }
+}
@@ -0,0 +1,477 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span, Text},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::preview::MAX_PREVIEW_LINES;
+use crate::sanitize::sanitize;
+
+/// Spaces per nesting level in pretty-printed JSON
+const JSON_INDENT: usize = 2;
+
+/// Table columns are cut to this width so one long cell can't push the rest off screen
+const MAX_COLUMN_WIDTH: usize = 30;
+
+/// Data formats that get a structured preview instead of plain highlighting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+    Csv,
+    Tsv,
+}
+
+impl DataFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "json" => Some(DataFormat::Json),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            "csv" => Some(DataFormat::Csv),
+            "tsv" | "tab" => Some(DataFormat::Tsv),
+            _ => None,
+        }
+    }
+
+    /// Whether a file cut short by the read limit can still be previewed. Rows of a
+    /// table stand on their own, a document missing its end doesn't parse.
+    pub fn allows_partial(self) -> bool {
+        matches!(self, DataFormat::Csv | DataFormat::Tsv)
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DataFormat::Json => "JSON",
+            DataFormat::Yaml => "YAML",
+            DataFormat::Toml => "TOML",
+            DataFormat::Csv => "CSV",
+            DataFormat::Tsv => "TSV",
+        })
+    }
+}
+
+/// Where and why a file failed to parse, with 1-based line and column
+#[derive(Debug)]
+pub struct ParseError {
+    pub format: DataFormat,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    /// Locates an error reported as a byte offset into `content`
+    fn at_offset(format: DataFormat, content: &str, offset: usize, message: String) -> Self {
+        let before = &content[..offset.min(content.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        ParseError {
+            format,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} parse error at line {}, column {}: {}",
+            self.format, self.line, self.column, self.message
+        )
+    }
+}
+
+/// A CSV or TSV file laid out as a table
+pub struct TableData {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// Display width of each column, capped at `MAX_COLUMN_WIDTH`
+    pub widths: Vec<u16>,
+}
+
+impl TableData {
+    /// `cells` cut or padded to the widths of their columns, as the table draws them
+    pub fn laid_out(&self, cells: &[String]) -> Vec<String> {
+        cells
+            .iter()
+            .zip(&self.widths)
+            .map(|(cell, &width)| fit(&sanitize(cell), width as usize))
+            .collect()
+    }
+
+    /// Each row as one line laid out like the table, which is what searches look through
+    pub fn row_lines(&self) -> Vec<Line<'static>> {
+        self.rows
+            .iter()
+            .map(|row| Line::from(self.laid_out(row).join(" ")))
+            .collect()
+    }
+}
+
+/// `text` cut to at most `width` columns and padded with spaces up to it
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let ch_width = ch.width().unwrap_or(0);
+        if used + ch_width > width {
+            break;
+        }
+        fitted.push(ch);
+        used += ch_width;
+    }
+    fitted.push_str(&" ".repeat(width - used));
+    fitted
+}
+
+/// What a data file is previewed as
+pub enum Structured {
+    Text(Text<'static>),
+    Table(TableData),
+    /// The document written out again with consistent layout, to be highlighted as its
+    /// own format
+    Reformatted(String),
+}
+
+/// Parses `content` as `format`. JSON is pretty-printed with containers nested deeper
+/// than `collapse_depth` folded away, CSV and TSV become tables, and YAML and TOML are
+/// written back out in their own syntax with uniform indentation.
+pub fn parse(
+    format: DataFormat,
+    content: &str,
+    collapse_depth: Option<usize>,
+) -> Result<Structured, ParseError> {
+    match format {
+        DataFormat::Json => {
+            let value: Value = serde_json::from_str(content).map_err(|e| ParseError {
+                format,
+                line: e.line(),
+                column: e.column(),
+                message: strip_location(&e.to_string()),
+            })?;
+            Ok(Structured::Text(json_preview(&value, collapse_depth)))
+        }
+        DataFormat::Yaml => {
+            let documents = serde_yaml::Deserializer::from_str(content)
+                .map(|document| {
+                    let value = serde_yaml::Value::deserialize(document)?;
+                    serde_yaml::to_string(&value)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    let (line, column) = e
+                        .location()
+                        .map_or((1, 1), |location| (location.line(), location.column()));
+                    ParseError {
+                        format,
+                        line,
+                        column,
+                        message: strip_location(&e.to_string()),
+                    }
+                })?;
+            Ok(Structured::Reformatted(documents.join("---\n")))
+        }
+        DataFormat::Toml => {
+            let table = toml::from_str::<toml::Table>(content).map_err(|e| {
+                let offset = e.span().map_or(0, |span| span.start);
+                ParseError::at_offset(format, content, offset, e.message().to_string())
+            })?;
+            let pretty = toml::to_string_pretty(&table).map_err(|e| ParseError {
+                format,
+                line: 1,
+                column: 1,
+                message: e.to_string(),
+            })?;
+            Ok(Structured::Reformatted(pretty))
+        }
+        DataFormat::Csv => csv_table(content, b',').map(Structured::Table),
+        DataFormat::Tsv => csv_table(content, b'\t').map(Structured::Table),
+    }
+}
+
+/// serde_json and serde_yaml end their messages with the position, which is shown
+/// separately
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message.to_string(),
+    }
+}
+
+fn csv_table(content: &str, delimiter: u8) -> Result<TableData, ParseError> {
+    let format = if delimiter == b'\t' {
+        DataFormat::Tsv
+    } else {
+        DataFormat::Csv
+    };
+    let to_error = |e: csv::Error| {
+        let offset = e.position().map_or(0, |position| position.byte() as usize);
+        ParseError::at_offset(format, content, offset, e.to_string())
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let header: Vec<String> = reader
+        .headers()
+        .map_err(to_error)?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let mut rows = Vec::new();
+    for record in reader.records().take(MAX_PREVIEW_LINES) {
+        rows.push(
+            record
+                .map_err(to_error)?
+                .iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    let columns = rows
+        .iter()
+        .map(Vec::len)
+        .chain([header.len()])
+        .max()
+        .unwrap_or(0);
+    let widths = (0..columns)
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.width())
+                .max()
+                .unwrap_or(0)
+                .clamp(1, MAX_COLUMN_WIDTH) as u16
+        })
+        .collect();
+
+    Ok(TableData {
+        header,
+        rows,
+        widths,
+    })
+}
+
+/// Pretty-prints `value` with keys, strings, numbers and literals colored apart.
+/// Non-empty objects and arrays at `collapse_depth` are folded into a one line summary.
+fn json_preview(value: &Value, collapse_depth: Option<usize>) -> Text<'static> {
+    let mut writer = JsonWriter {
+        lines: Vec::new(),
+        current: Vec::new(),
+        collapse_depth,
+    };
+    writer.value(value, 0);
+    writer.newline();
+    Text::from(writer.lines)
+}
+
+struct JsonWriter {
+    lines: Vec<Line<'static>>,
+    current: Vec<Span<'static>>,
+    collapse_depth: Option<usize>,
+}
+
+impl JsonWriter {
+    fn full(&self) -> bool {
+        self.lines.len() >= MAX_PREVIEW_LINES
+    }
+
+    fn push(&mut self, text: impl Into<String>, style: Style) {
+        self.current.push(Span::styled(text.into(), style));
+    }
+
+    fn newline(&mut self) {
+        let spans = std::mem::take(&mut self.current);
+        if !self.full() {
+            self.lines.push(Line::from(spans));
+        }
+    }
+
+    fn indent(&mut self, depth: usize) {
+        self.push(" ".repeat(depth * JSON_INDENT), Style::default());
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) {
+        let collapsed = self.collapse_depth.is_some_and(|max| depth >= max);
+        match value {
+            Value::Object(map) if map.is_empty() => self.push("{}", Style::default()),
+            Value::Array(items) if items.is_empty() => self.push("[]", Style::default()),
+            Value::Object(map) if collapsed => {
+                self.push("{…}", Style::default());
+                self.push(format!(" {}", count(map.len(), "key")), dim());
+            }
+            Value::Array(items) if collapsed => {
+                self.push("[…]", Style::default());
+                self.push(format!(" {}", count(items.len(), "item")), dim());
+            }
+            Value::Object(map) => {
+                self.push("{", Style::default());
+                self.newline();
+                for (i, (key, item)) in map.iter().enumerate() {
+                    if self.full() {
+                        return;
+                    }
+                    self.indent(depth + 1);
+                    self.push(quoted(key), Style::default().fg(Color::Blue));
+                    self.push(": ", Style::default());
+                    self.value(item, depth + 1);
+                    if i + 1 < map.len() {
+                        self.push(",", Style::default());
+                    }
+                    self.newline();
+                }
+                self.indent(depth);
+                self.push("}", Style::default());
+            }
+            Value::Array(items) => {
+                self.push("[", Style::default());
+                self.newline();
+                for (i, item) in items.iter().enumerate() {
+                    if self.full() {
+                        return;
+                    }
+                    self.indent(depth + 1);
+                    self.value(item, depth + 1);
+                    if i + 1 < items.len() {
+                        self.push(",", Style::default());
+                    }
+                    self.newline();
+                }
+                self.indent(depth);
+                self.push("]", Style::default());
+            }
+            Value::String(s) => self.push(quoted(s), Style::default().fg(Color::Green)),
+            Value::Number(n) => self.push(n.to_string(), Style::default().fg(Color::Yellow)),
+            Value::Bool(b) => self.push(b.to_string(), Style::default().fg(Color::Magenta)),
+            Value::Null => self.push("null", dim()),
+        }
+    }
+}
+
+fn count(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
+
+fn dim() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+/// A string as a JSON literal, with quotes and escapes
+fn quoted(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("\"{s}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::plain_lines;
+
+    fn text_of(structured: Structured) -> Vec<String> {
+        match structured {
+            Structured::Text(text) => plain_lines(&text.lines),
+            Structured::Reformatted(pretty) => pretty.lines().map(str::to_string).collect(),
+            Structured::Table(_) => panic!("expected text, got a table"),
+        }
+    }
+
+    fn error_at(format: DataFormat, content: &str) -> (usize, usize) {
+        match parse(format, content, None) {
+            Err(error) => (error.line, error.column),
+            Ok(_) => panic!("{content:?} parsed as {format}"),
+        }
+    }
+
+    #[test]
+    fn json_is_pretty_printed() {
+        let parsed = parse(DataFormat::Json, r#"{"a":[1,true],"b":null}"#, None).unwrap();
+        assert_eq!(
+            text_of(parsed),
+            [
+                "{",
+                r#"  "a": ["#,
+                "    1,",
+                "    true",
+                "  ],",
+                r#"  "b": null"#,
+                "}"
+            ]
+        );
+    }
+
+    #[test]
+    fn json_folds_below_the_collapse_depth() {
+        let json = r#"{"a":{"b":{"c":1}},"d":[1,2,3],"e":{}}"#;
+        let parsed = parse(DataFormat::Json, json, Some(1)).unwrap();
+        assert_eq!(
+            text_of(parsed),
+            [
+                "{",
+                r#"  "a": {…} 1 key,"#,
+                r#"  "d": […] 3 items,"#,
+                r#"  "e": {}"#,
+                "}"
+            ]
+        );
+        let parsed = parse(DataFormat::Json, json, Some(0)).unwrap();
+        assert_eq!(text_of(parsed), ["{…} 3 keys"]);
+    }
+
+    #[test]
+    fn csv_widths_fit_the_widest_cell() {
+        let csv = "name,note\nal,short\nbo,\"quoted, with a comma\"\n";
+        let Structured::Table(table) = parse(DataFormat::Csv, csv, None).unwrap() else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.header, ["name", "note"]);
+        assert_eq!(table.rows[1], ["bo", "quoted, with a comma"]);
+        assert_eq!(table.widths, [4, 20]);
+    }
+
+    #[test]
+    fn csv_columns_are_capped_and_rows_may_differ_in_length() {
+        let long = "x".repeat(MAX_COLUMN_WIDTH + 10);
+        let tsv = format!("a\tb\n1\n2\t{long}\t3\n");
+        let Structured::Table(table) = parse(DataFormat::Tsv, &tsv, None).unwrap() else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.widths, [1, MAX_COLUMN_WIDTH as u16, 1]);
+        assert_eq!(table.laid_out(&table.rows[0]), ["1"]);
+    }
+
+    #[test]
+    fn yaml_and_toml_are_reformatted() {
+        let yaml = parse(DataFormat::Yaml, "a:   1\nb:\n    - x\n---\nc: 2\n", None).unwrap();
+        assert_eq!(text_of(yaml), ["a: 1", "b:", "- x", "---", "c: 2"]);
+        let toml = parse(DataFormat::Toml, "b={c=1}\na=[1,2]", None).unwrap();
+        assert!(text_of(toml).contains(&"[b]".to_string()));
+    }
+
+    #[test]
+    fn errors_point_at_their_line_and_column() {
+        assert_eq!(
+            error_at(DataFormat::Json, "{\n  \"a\": 1,\n  oops\n}"),
+            (3, 3)
+        );
+        assert_eq!(error_at(DataFormat::Yaml, "a: 1\nb: [1, 2\nc: 3\n"), (3, 2));
+        assert_eq!(error_at(DataFormat::Toml, "a = 1\nb = = 2\n"), (2, 5));
+    }
+}
@@ -0,0 +1,180 @@
+use ignore::WalkBuilder;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::path::{Path, PathBuf};
+
+use crate::filesystem::human_size;
+
+/// How many levels below the selected directory are listed, like `tree -L 2`
+pub const DEFAULT_TREE_DEPTH: usize = 2;
+
+/// Stops huge directories from stalling the preview
+const MAX_TREE_ENTRIES: usize = 1000;
+
+struct TreeEntry {
+    path: PathBuf,
+    depth: usize,
+    is_dir: bool,
+    size: u64,
+}
+
+/// Renders `root` as an indented tree, respecting .gitignore and hidden files the same
+/// way `collect_files` does.
+pub fn tree_preview(root: &Path, max_depth: usize) -> Text<'static> {
+    let mut entries: Vec<TreeEntry> = WalkBuilder::new(root)
+        .max_depth(Some(max_depth))
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.depth() > 0)
+        // One past the limit tells a directory that was cut short from one that fits
+        .take(MAX_TREE_ENTRIES + 1)
+        .map(|entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            let size = if is_dir {
+                0
+            } else {
+                entry.metadata().map(|m| m.len()).unwrap_or(0)
+            };
+            TreeEntry {
+                path: entry.into_path(),
+                depth: 0,
+                is_dir,
+                size,
+            }
+        })
+        .collect();
+    let truncated = entries.len() > MAX_TREE_ENTRIES;
+    entries.truncate(MAX_TREE_ENTRIES);
+
+    // WalkBuilder's depth is relative to the root it was given, recompute it from the
+    // path so symlinked roots don't confuse the indentation
+    for entry in entries.iter_mut() {
+        entry.depth = entry
+            .path
+            .strip_prefix(root)
+            .map(|rel| rel.components().count())
+            .unwrap_or(1);
+    }
+
+    let dir_style = Style::default()
+        .fg(Color::Blue)
+        .add_modifier(Modifier::BOLD);
+    let dim = Style::default().fg(Color::DarkGray);
+
+    let mut lines = vec![Line::from(Span::styled(
+        root.to_string_lossy().into_owned(),
+        dir_style,
+    ))];
+
+    let mut ancestors_last: Vec<bool> = Vec::new();
+    let (mut dir_count, mut file_count, mut total_size) = (0usize, 0usize, 0u64);
+
+    for (i, entry) in entries.iter().enumerate() {
+        let is_last = entries[i + 1..]
+            .iter()
+            .find(|next| next.depth <= entry.depth)
+            .is_none_or(|next| next.depth < entry.depth);
+
+        ancestors_last.truncate(entry.depth.saturating_sub(1));
+        let mut prefix: String = ancestors_last
+            .iter()
+            .map(|last| if *last { "    " } else { "│   " })
+            .collect();
+        prefix.push_str(if is_last { "└── " } else { "├── " });
+        ancestors_last.push(is_last);
+
+        let name = entry
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut spans = vec![Span::styled(prefix, dim)];
+        if entry.is_dir {
+            dir_count += 1;
+            spans.push(Span::styled(format!("{name}/"), dir_style));
+            spans.push(Span::styled(
+                format!("  ({} entries)", count_children(&entry.path)),
+                dim,
+            ));
+        } else {
+            file_count += 1;
+            total_size += entry.size;
+            spans.push(Span::raw(name));
+            spans.push(Span::styled(format!("  {}", human_size(entry.size)), dim));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    lines.push(Line::from(""));
+    let mut summary = format!(
+        "{dir_count} directories, {file_count} files, {}",
+        human_size(total_size)
+    );
+    if truncated {
+        summary.push_str(" (truncated)");
+    }
+    lines.push(Line::from(Span::styled(summary, dim)));
+
+    Text::from(lines)
+}
+
+/// Number of visible entries directly inside `dir`
+fn count_children(dir: &Path) -> usize {
+    WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.depth() == 1)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+
+    fn listed(root: &Path, max_depth: usize) -> Vec<String> {
+        plain_lines(&tree_preview(root, max_depth).lines)
+    }
+
+    #[test]
+    fn lists_entries_as_a_tree() {
+        let dir = temp_dir_with(&[("a/b/deep.txt", b""), ("a/one.txt", b"1"), ("z.txt", b"22")]);
+        let lines = listed(dir.path(), 2);
+        assert_eq!(
+            lines[1..],
+            [
+                "├── a/  (2 entries)",
+                "│   ├── b/  (1 entries)",
+                "│   └── one.txt  1B",
+                "└── z.txt  2B",
+                "",
+                "2 directories, 2 files, 3B",
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_only_when_entries_were_left_out() {
+        let names: Vec<String> = (0..MAX_TREE_ENTRIES).map(|n| format!("{n:04}")).collect();
+        let files: Vec<(&str, &[u8])> = names
+            .iter()
+            .map(|name| (name.as_str(), b"".as_slice()))
+            .collect();
+        let dir = temp_dir_with(&files);
+        let summary = listed(dir.path(), 1).pop().unwrap();
+        assert_eq!(
+            summary,
+            format!("0 directories, {MAX_TREE_ENTRIES} files, 0B")
+        );
+
+        std::fs::write(dir.path().join("extra"), "").unwrap();
+        let summary = listed(dir.path(), 1).pop().unwrap();
+        assert!(summary.ends_with(" (truncated)"));
+        assert!(summary.starts_with(&format!("0 directories, {MAX_TREE_ENTRIES} files")));
+    }
+}
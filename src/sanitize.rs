@@ -0,0 +1,109 @@
+use ratatui::{
+    style::Modifier,
+    text::{Line, Span},
+};
+use std::borrow::Cow;
+
+/// The visible stand-in for a control character, or `None` if `ch` is safe to print.
+/// Escapes become `^[`, carriage returns `␍`, other C0 controls caret notation, and
+/// C1 controls (which some terminals treat like ESC sequences) their hex code.
+pub fn control_picture(ch: char) -> Option<String> {
+    match ch {
+        '\r' => Some("␍".to_string()),
+        '\x7f' => Some("^?".to_string()),
+        '\0'..='\x1f' => Some(format!("^{}", (ch as u8 + b'@') as char)),
+        '\u{80}'..='\u{9f}' => Some(format!("<{:02x}>", ch as u32)),
+        _ => None,
+    }
+}
+
+/// Replaces every control character in `text` with its visible stand-in
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|ch| control_picture(ch).is_some()) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut clean = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match control_picture(ch) {
+            Some(picture) => clean.push_str(&picture),
+            None => clean.push(ch),
+        }
+    }
+    Cow::Owned(clean)
+}
+
+/// Sanitizes a styled line, drawing the stand-ins in reverse video so that they can't be
+/// mistaken for the file's real content
+pub fn sanitize_line(line: &Line<'_>) -> Line<'static> {
+    let mut spans = Vec::with_capacity(line.spans.len());
+    for span in &line.spans {
+        let mut plain = String::new();
+        for ch in span.content.chars() {
+            match control_picture(ch) {
+                Some(picture) => {
+                    if !plain.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut plain), span.style));
+                    }
+                    spans.push(Span::styled(
+                        picture,
+                        span.style.add_modifier(Modifier::REVERSED),
+                    ));
+                }
+                None => plain.push(ch),
+            }
+        }
+        if !plain.is_empty() {
+            spans.push(Span::styled(plain, span.style));
+        }
+    }
+    let mut sanitized = Line::from(spans).style(line.style);
+    sanitized.alignment = line.alignment;
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::expand_tabs;
+
+    #[test]
+    fn escape_sequences_are_shown_not_sent() {
+        assert_eq!(sanitize("\x1b[31mred\x1b[0m"), "^[[31mred^[[0m");
+        assert_eq!(sanitize("\x1b]52;c;aGk=\x07"), "^[]52;c;aGk=^G");
+    }
+
+    #[test]
+    fn c1_controls_del_and_carriage_returns() {
+        assert_eq!(sanitize("\u{9b}2J"), "<9b>2J");
+        assert_eq!(sanitize("\u{80}\u{9f}"), "<80><9f>");
+        assert_eq!(sanitize("a\x7fb"), "a^?b");
+        assert_eq!(sanitize("progress\roverwritten"), "progress␍overwritten");
+        assert_eq!(sanitize("\0"), "^@");
+    }
+
+    #[test]
+    fn printable_text_is_borrowed_untouched() {
+        assert!(matches!(sanitize("plain ünïcödé ✓"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn tabs_are_expanded_before_sanitizing() {
+        assert_eq!(sanitize("a\tb"), "a^Ib");
+        assert_eq!(sanitize(&expand_tabs("a\tb", 4)), "a   b");
+    }
+
+    #[test]
+    fn stand_ins_are_reversed_and_keep_the_span_style() {
+        let style = ratatui::style::Style::default().fg(ratatui::style::Color::Red);
+        let line = sanitize_line(&Line::from(Span::styled("a\x1bb", style)));
+        let contents: Vec<&str> = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(contents, ["a", "^[", "b"]);
+        assert_eq!(line.spans[1].style, style.add_modifier(Modifier::REVERSED));
+        assert_eq!(line.spans[2].style, style);
+    }
+}
@@ -0,0 +1,32 @@
+//! Fixtures shared by the unit tests
+
+use ratatui::text::Line;
+use std::fs;
+use tempfile::TempDir;
+
+/// A temporary directory holding `files`, given as relative path and contents. It is
+/// removed when dropped, and every test gets its own so they can run in parallel.
+pub fn temp_dir_with(files: &[(&str, &[u8])]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, contents) in files {
+        let path = dir.path().join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+/// The text of each line without its styling
+pub fn plain_lines(lines: &[Line<'_>]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| {
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        })
+        .collect()
+}
@@ -1,40 +1,120 @@
 use nucleo::{Matcher, Utf32Str};
-use ratatui::style::{Color, Style};
-use ratatui::text::{Line, Span, Text};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::highlight::highlight_contents;
+use crate::git::status_markers;
+use crate::highlight::next_theme;
+use crate::preview::cache::{CacheKey, PreviewCache};
+use crate::preview::follow::Follower;
+use crate::preview::prefetch::Prefetcher;
+use crate::preview::search::{SearchMatch, find_matches, match_columns};
+use crate::preview::{
+    GitView, MAX_PREVIEW_LINES, Preview, PreviewOptions, error_preview, pending_preview,
+    render_preview, source_lines,
+};
+
+/// How many results either side of the selection get their previews prepared ahead
+const PREFETCH_NEIGHBOURS: usize = 3;
+
+/// Deepest level Ctrl+E folds JSON previews at before expanding them fully again
+const MAX_COLLAPSE_DEPTH: usize = 4;
 
 pub enum Focus {
     SearchBar,
     Results,
+    Preview,
+}
+
+/// A search within the preview pane, opened with `/` while the preview is focused
+pub struct PreviewSearch {
+    pub pattern: String,
+    /// The pattern is still being typed
+    pub editing: bool,
+    pub matches: Vec<SearchMatch>,
+    /// Index into `matches` of the one jumped to
+    pub current: usize,
 }
-pub struct AppState<'a> {
+
+pub struct AppState {
+    /// Directory the search is rooted at
+    pub root: PathBuf,
     pub query: String,
     pub filtered_files: Vec<(PathBuf, String, Vec<u32>)>,
     pub focus: Focus,
     pub selected_idx: usize,
     pub scroll_offset: u16,
     pub selected_path: Option<PathBuf>,
-    pub preview_cache: HashMap<PathBuf, Text<'a>>,
+    pub preview_cache: PreviewCache,
+    /// The preview for `selected_path` as it is shown right now
+    pub preview: Option<Arc<Preview>>,
+    pub prefetcher: Prefetcher,
+    /// The selection's preview while its preview command runs on the prefetcher, to be
+    /// shown once it finishes
+    pending: Option<CacheKey>,
     pub curr_preview_height: u16,
     pub curr_preview_width: u16,
+    pub preview_opts: PreviewOptions,
+    /// Columns the preview is scrolled to the right by when wrapping is off
+    pub preview_hscroll: u16,
+    /// Lines of the preview scrolled past at the top
+    pub preview_scroll: usize,
+    /// Lines of the selected file's text that are rendered, raised when a search match
+    /// lies past the end of them
+    preview_lines: usize,
+    /// The preview line the cursor is on while the preview has focus
+    pub preview_cursor: usize,
+    /// Where a range of preview lines started to be selected, the cursor being the other end
+    pub selection_anchor: Option<usize>,
+    pub search: Option<PreviewSearch>,
+    /// Results marked to be opened together, kept while the query changes
+    pub marked: HashSet<PathBuf>,
+    /// Enter hands the chosen paths back to be printed instead of opening them
+    pub print_mode: bool,
+    /// Follow mode: the preview shows the end of the file and grows with it
+    pub follow: bool,
+    follower: Option<Follower>,
+    /// `git status` letters for changed files, `None` outside of a git repository
+    pub git_status: Option<HashMap<PathBuf, char>>,
 }
 
-impl<'a> AppState<'a> {
-    pub fn new(all_files: &[PathBuf], matcher: &mut nucleo::Matcher) -> Self {
+impl AppState {
+    pub fn new(
+        root: PathBuf,
+        all_files: &[PathBuf],
+        matcher: &mut nucleo::Matcher,
+        preview_opts: PreviewOptions,
+        cache_budget: usize,
+    ) -> Self {
         let mut buf = Vec::new(); // Local buffer for UTF32 conversion
+        let git_status = status_markers(&root);
         let mut state = AppState {
+            root,
             query: String::new(),
             filtered_files: Vec::new(),
             focus: Focus::SearchBar,
             scroll_offset: 0,
             selected_idx: 0,
-            preview_cache: HashMap::new(),
+            preview_cache: PreviewCache::new(cache_budget),
+            preview: None,
+            prefetcher: Prefetcher::spawn(),
+            pending: None,
             selected_path: None,
             curr_preview_height: 0,
             curr_preview_width: 0,
+            preview_opts,
+            preview_hscroll: 0,
+            preview_scroll: 0,
+            preview_lines: MAX_PREVIEW_LINES,
+            preview_cursor: 0,
+            selection_anchor: None,
+            search: None,
+            marked: HashSet::new(),
+            print_mode: false,
+            follow: false,
+            follower: None,
+            git_status,
         };
 
         state.update_filtered_files(nucleo::Utf32Str::new("", &mut buf), all_files, matcher);
@@ -70,7 +150,7 @@ impl<'a> AppState<'a> {
             .collect::<Vec<_>>();
 
         // Sort by descending score
-        scored_files.sort_by(|a, b| b.0.cmp(&a.0));
+        scored_files.sort_by_key(|f| std::cmp::Reverse(f.0));
         self.filtered_files = scored_files
             .into_iter()
             .map(|(_, path, n, i)| (path, n, i))
@@ -78,55 +158,874 @@ impl<'a> AppState<'a> {
     }
 
     pub fn update_preview(&mut self) {
+        self.refresh_preview(false);
+    }
+
+    /// Shows the preview of the selection and has the previews around it prepared, along
+    /// with the whole first page when `results_changed` because they were just re-filtered
+    pub fn refresh_preview(&mut self, results_changed: bool) {
+        self.load_preview();
+        self.refresh_search();
+        self.prefetch(results_changed);
+    }
+
+    fn load_preview(&mut self) {
+        self.collect_prefetched();
+        self.pending = None;
         if let Some((path, _, _)) = self.filtered_files.get(self.selected_idx) {
-            if self.selected_path.as_ref() != Some(path) || !self.preview_cache.contains_key(path) {
-                self.selected_path = Some(path.clone());
+            let path_changed = self.selected_path.as_ref() != Some(path);
+            if path_changed {
+                self.preview_hscroll = 0;
+                self.preview_scroll = 0;
+                self.preview_lines = MAX_PREVIEW_LINES;
+                self.preview_cursor = 0;
+                self.selection_anchor = None;
             }
             self.selected_path = Some(path.clone());
 
-            let mut lines_for_no_preview: Vec<Line<'static>> = Vec::new();
-            let no_preview_text = "No Preview available";
-            let prev_width = self.curr_preview_width;
-            let prev_height = self.curr_preview_height;
-
-            let mut first_line_spans = Vec::new();
-            first_line_spans.push(Span::styled(
-                no_preview_text.to_string(),
-                Style::default().fg(Color::DarkGray),
-            ));
-
-            let current_len = no_preview_text.len();
-            if current_len < prev_width as usize {
-                first_line_spans.push(Span::styled(
-                    " ".repeat(prev_width as usize - current_len),
-                    Style::default(),
-                ));
+            if self.follow {
+                if self
+                    .follower
+                    .as_ref()
+                    .is_none_or(|f| f.path() != path.as_path())
+                {
+                    self.follower = Follower::open(path, &self.preview_opts).ok();
+                }
+                if let Some(follower) = &self.follower {
+                    self.preview = Some(Arc::new(follower.preview()));
+                    self.scroll_to_end();
+                    return;
+                }
             }
-            lines_for_no_preview.push(Line::from(first_line_spans));
 
-            while (lines_for_no_preview.len() as u16) < prev_height {
-                lines_for_no_preview.push(Line::from(Span::styled(
-                    " ".repeat(prev_width as usize),
-                    Style::default(),
-                )));
+            let key = self.cache_key(path);
+            if let Some(preview) = self.preview_cache.get(&key) {
+                self.preview = Some(preview);
+                return;
             }
-            if !self.preview_cache.contains_key(path) {
-                if let Ok(content) = std::fs::read_to_string(path) {
-                    let highlighted = highlight_contents(
-                        path,
-                        &content,
-                        self.curr_preview_height,
-                        self.curr_preview_width,
-                    );
-                    self.preview_cache.insert(path.clone(), highlighted);
-                } else {
-                    self.preview_cache
-                        .insert(path.clone(), Text::from(lines_for_no_preview));
+
+            // Preview commands can take a while, so they run on the prefetcher where the
+            // next request kills them, and the last preview of the same file stays up
+            if self.preview_opts.command_for(path).is_some() {
+                if path_changed || self.preview.is_none() {
+                    self.preview = Some(Arc::new(pending_preview()));
                 }
+                self.pending = Some(key);
+                return;
+            }
+
+            if let Some(preview) = render_preview(&key, &self.preview_opts, &|| false) {
+                let preview = Arc::new(preview);
+                self.preview_cache.insert(key, preview.clone());
+                self.preview = Some(preview);
             }
         } else {
             self.selected_path = None;
-            self.preview_cache.clear();
+            self.preview = None;
+        }
+    }
+
+    /// Whether the selection's preview command is still running
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Shows the selection's preview once its command finished, returning whether it did
+    pub fn poll_pending(&mut self) -> bool {
+        let Some(key) = self.pending.clone() else {
+            return false;
+        };
+        self.collect_prefetched();
+        if !self.preview_cache.contains(&key) {
+            return false;
+        }
+        self.load_preview();
+        self.refresh_search();
+        true
+    }
+
+    /// Asks the prefetcher for the previews `prefetch_keys` lists
+    fn prefetch(&mut self, include_first_page: bool) {
+        let keys = self.prefetch_keys(include_first_page);
+        self.prefetcher.request(keys, &self.preview_opts);
+    }
+
+    /// The selection's pending preview command first, then the results around the
+    /// selection, and the whole first page when `include_first_page`. Anything already
+    /// cached is skipped.
+    fn prefetch_keys(&self, include_first_page: bool) -> Vec<CacheKey> {
+        let mut indices = Vec::new();
+        // Walking a file's history or blaming it is too slow to do for files that may
+        // not be looked at
+        let neighbours = if self.preview_opts.git_view.is_some() {
+            0
+        } else {
+            PREFETCH_NEIGHBOURS
+        };
+        for offset in 1..=neighbours {
+            indices.push(self.selected_idx + offset);
+            if let Some(before) = self.selected_idx.checked_sub(offset) {
+                indices.push(before);
+            }
+        }
+        if include_first_page && neighbours > 0 {
+            let first = self.scroll_offset as usize;
+            indices.extend(first..first + self.curr_preview_height as usize);
+        }
+
+        let mut keys: Vec<CacheKey> = self.pending.iter().cloned().collect();
+        for idx in indices {
+            if idx == self.selected_idx {
+                continue;
+            }
+            let Some((path, _, _)) = self.filtered_files.get(idx) else {
+                continue;
+            };
+            let key = self.cache_key(path);
+            if !self.preview_cache.contains(&key) && !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    /// The key `path` is cached under right now
+    fn cache_key(&self, path: &Path) -> CacheKey {
+        let query = if self.preview_opts.query_dependent(path) {
+            self.query.as_str()
+        } else {
+            ""
+        };
+        // The line in focus is the cursor's, which only the selection's preview has
+        let selected = self.selected_path.as_deref() == Some(path);
+        let line = if self.preview_opts.line_dependent(path) && selected {
+            self.preview_cursor + 1
+        } else {
+            1
+        };
+        let lines = if selected {
+            self.preview_lines
+        } else {
+            MAX_PREVIEW_LINES
+        };
+        CacheKey::new(
+            path,
+            query,
+            line,
+            lines,
+            self.curr_preview_width,
+            self.curr_preview_height,
+        )
+    }
+
+    /// Moves previews the prefetcher has finished into the cache
+    pub fn collect_prefetched(&mut self) {
+        for (key, preview) in self.prefetcher.finished() {
+            self.preview_cache.insert(key, preview);
+        }
+    }
+
+    /// Records the size of the preview pane, re-rendering if it changed since previews
+    /// cached for the old size are no longer wanted
+    pub fn set_preview_size(&mut self, width: u16, height: u16) {
+        if (width, height) == (self.curr_preview_width, self.curr_preview_height) {
+            return;
+        }
+        self.curr_preview_width = width;
+        self.curr_preview_height = height;
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Moves focus into the preview, or back out of it to the results
+    pub fn toggle_preview_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::Preview => Focus::Results,
+            Focus::SearchBar | Focus::Results => Focus::Preview,
+        };
+    }
+
+    /// Forgets cached previews of `paths`, e.g. after they have been edited
+    pub fn invalidate_previews(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            self.preview_cache.invalidate(path);
+        }
+        self.update_preview();
+    }
+
+    /// Switches previews to the next available theme
+    pub fn cycle_theme(&mut self) {
+        self.preview_opts.theme = next_theme(&self.preview_opts.theme);
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Flips modified files between their diff against HEAD and their full contents
+    pub fn toggle_diff(&mut self) {
+        self.preview_opts.show_diff = !self.preview_opts.show_diff;
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Steps through the contents, history and blame views of the selected file
+    pub fn cycle_git_view(&mut self) {
+        self.preview_opts.git_view = match self.preview_opts.git_view {
+            None => Some(GitView::History),
+            Some(GitView::History) => Some(GitView::Blame),
+            Some(GitView::Blame) => None,
+        };
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Switches data files between their structured preview and their plain text
+    pub fn toggle_structured(&mut self) {
+        self.preview_opts.structured = !self.preview_opts.structured;
+        self.preview_hscroll = 0;
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Folds JSON previews one level deeper each time, back to fully expanded after
+    /// `MAX_COLLAPSE_DEPTH`
+    pub fn cycle_collapse_depth(&mut self) {
+        self.preview_opts.collapse_depth = match self.preview_opts.collapse_depth {
+            None => Some(1),
+            Some(depth) if depth < MAX_COLLAPSE_DEPTH => Some(depth + 1),
+            Some(_) => None,
+        };
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    pub fn toggle_metadata(&mut self) {
+        self.preview_opts.show_metadata = !self.preview_opts.show_metadata;
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.preview_opts.wrap = !self.preview_opts.wrap;
+        self.preview_hscroll = 0;
+    }
+
+    /// Marks or unmarks the selected result and moves on to the next one
+    pub fn toggle_mark(&mut self) {
+        let Some((path, _, _)) = self.filtered_files.get(self.selected_idx) else {
+            return;
+        };
+        if !self.marked.remove(path) {
+            self.marked.insert(path.clone());
+        }
+        if self.selected_idx + 1 < self.filtered_files.len() {
+            self.selected_idx += 1;
+        }
+        self.marks_changed();
+    }
+
+    /// Marks every result matching the current query
+    pub fn mark_all(&mut self) {
+        self.marked
+            .extend(self.filtered_files.iter().map(|(path, _, _)| path.clone()));
+        self.marks_changed();
+    }
+
+    /// Flips the marks of the results matching the current query
+    pub fn invert_marks(&mut self) {
+        for (path, _, _) in &self.filtered_files {
+            if !self.marked.remove(path) {
+                self.marked.insert(path.clone());
+            }
+        }
+        self.marks_changed();
+    }
+
+    /// Compares the marked files with each other when exactly two files are marked
+    fn marks_changed(&mut self) {
+        let mut files = self.marked.iter().filter(|path| path.is_file());
+        let pair = match (files.next(), files.next(), files.next()) {
+            (Some(a), Some(b), None) if self.marked.len() == 2 => {
+                let mut pair = [a.clone(), b.clone()];
+                pair.sort();
+                Some(pair)
+            }
+            _ => None,
+        };
+        if pair == self.preview_opts.compare_pair {
+            return;
+        }
+        self.preview_opts.compare_pair = pair;
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// The marked files in the order they were collected in, or else the selected one
+    pub fn chosen_paths(&self, all_files: &[PathBuf]) -> Vec<PathBuf> {
+        if self.marked.is_empty() {
+            return self
+                .filtered_files
+                .get(self.selected_idx)
+                .map(|(path, _, _)| vec![path.clone()])
+                .unwrap_or_default();
+        }
+        all_files
+            .iter()
+            .filter(|path| self.marked.contains(*path))
+            .cloned()
+            .collect()
+    }
+
+    /// Makes the selected file the one every other file is compared against, or stops
+    /// comparing if there already is one
+    pub fn toggle_compare_base(&mut self) {
+        self.preview_opts.compare_with = match self.preview_opts.compare_with {
+            Some(_) => None,
+            None => self.selected_path.clone(),
+        };
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Switches comparisons between a unified diff and two columns side by side
+    pub fn toggle_side_by_side(&mut self) {
+        self.preview_opts.side_by_side = !self.preview_opts.side_by_side;
+        self.prefetcher.cancel();
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Starts or stops following the end of the selected file as it grows
+    pub fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+        self.follower = None;
+        self.preview_scroll = 0;
+        self.preview_cursor = 0;
+        self.update_preview();
+    }
+
+    /// Whether a file is being followed, so the UI should check back for new lines
+    pub fn is_following(&self) -> bool {
+        self.follower.is_some()
+    }
+
+    /// Shows lines appended to the followed file, returning whether there were any. The
+    /// view sticks to the end unless it was scrolled up.
+    pub fn poll_follow(&mut self) -> bool {
+        let Some(follower) = &mut self.follower else {
+            return false;
+        };
+        // While the file is missing, e.g. mid-rotation, the last lines it had stay up
+        if !matches!(follower.poll(&self.preview_opts), Ok(true)) {
+            return false;
+        }
+        let lines = self
+            .preview
+            .as_ref()
+            .map_or(0, |preview| preview.text.lines.len());
+        let at_end = self.preview_cursor + 1 >= lines;
+        self.preview = Some(Arc::new(follower.preview()));
+        if at_end {
+            self.scroll_to_end();
+        }
+        self.refresh_search();
+        true
+    }
+
+    fn scroll_to_end(&mut self) {
+        let lines = self
+            .preview
+            .as_ref()
+            .map_or(0, |preview| preview.text.lines.len());
+        self.preview_cursor = lines.saturating_sub(1);
+        self.preview_scroll = lines.saturating_sub(self.curr_preview_height as usize);
+    }
+
+    /// Moves the preview cursor up or down by `delta` lines, scrolling to keep it visible
+    pub fn move_preview_cursor(&mut self, delta: isize) {
+        let target = self.preview_cursor.saturating_add_signed(delta);
+        self.load_lines_up_to(target);
+        let lines = self
+            .preview
+            .as_ref()
+            .map_or(0, |preview| preview.text.lines.len());
+        self.preview_cursor = target.min(lines.saturating_sub(1));
+        let height = (self.curr_preview_height as usize).max(1);
+        if self.preview_cursor < self.preview_scroll {
+            self.preview_scroll = self.preview_cursor;
+        } else if self.preview_cursor >= self.preview_scroll + height {
+            self.preview_scroll = self.preview_cursor + 1 - height;
         }
+        self.cursor_moved();
+    }
+
+    /// Re-runs a preview command that was given the line in focus
+    fn cursor_moved(&mut self) {
+        if let Some(path) = &self.selected_path
+            && self.preview_opts.line_dependent(path)
+        {
+            self.update_preview();
+        }
+    }
+
+    /// Starts selecting a range of lines at the cursor, or drops the selection
+    pub fn toggle_selection(&mut self) {
+        self.selection_anchor = match self.selection_anchor {
+            Some(_) => None,
+            None => Some(self.preview_cursor),
+        };
+    }
+
+    /// The preview lines that are selected: the range being selected or else the cursor line
+    pub fn selected_lines(&self) -> RangeInclusive<usize> {
+        let anchor = self.selection_anchor.unwrap_or(self.preview_cursor);
+        anchor.min(self.preview_cursor)..=anchor.max(self.preview_cursor)
+    }
+
+    /// The text of the selected lines, ending the selection. Where the preview lines are
+    /// the file's own they are copied from the file as written, tabs and all; anything
+    /// else is copied as shown.
+    pub fn yank_selection(&mut self) -> Option<String> {
+        let preview = self.preview.as_ref()?;
+        let range = self.selected_lines();
+        let source = match &self.selected_path {
+            Some(path) if preview.numbered => source_lines(path, range.clone()),
+            _ => None,
+        };
+        let text = match source {
+            Some(text) => text,
+            None => {
+                let lines: Vec<String> = preview
+                    .text
+                    .lines
+                    .get(range)?
+                    .iter()
+                    .map(|line| {
+                        line.spans
+                            .iter()
+                            .map(|span| span.content.as_ref())
+                            .collect()
+                    })
+                    .collect();
+                lines.join("\n")
+            }
+        };
+        self.selection_anchor = None;
+        Some(text)
+    }
+
+    /// The line of the file the preview cursor is on, when the previewed lines are the
+    /// file's own lines rather than a diff, a rendering or a listing
+    pub fn cursor_file_line(&self) -> Option<usize> {
+        let preview = self.preview.as_ref()?;
+        (matches!(self.focus, Focus::Preview) && preview.numbered)
+            .then_some(self.preview_cursor + 1)
+    }
+
+    /// Starts typing a new search in the preview. Previews driven by the query, such as
+    /// a `{q}` grep command, start out searching for the query.
+    pub fn open_preview_search(&mut self) {
+        let query_driven = self
+            .selected_path
+            .as_ref()
+            .is_some_and(|path| self.preview_opts.query_dependent(path));
+        let pattern = if query_driven {
+            self.query.clone()
+        } else {
+            String::new()
+        };
+        self.search = Some(PreviewSearch {
+            pattern,
+            editing: true,
+            matches: Vec::new(),
+            current: 0,
+        });
+        self.search_changed();
+    }
+
+    pub fn close_preview_search(&mut self) {
+        self.search = None;
+    }
+
+    pub fn edit_preview_search(&mut self, edit: impl FnOnce(&mut String)) {
+        if let Some(search) = &mut self.search {
+            edit(&mut search.pattern);
+            self.search_changed();
+        }
+    }
+
+    pub fn finish_preview_search(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.editing = false;
+        }
+    }
+
+    /// Jumps `delta` matches forwards or backwards, wrapping around at either end
+    pub fn jump_to_match(&mut self, delta: isize) {
+        if let Some(search) = &mut self.search
+            && !search.matches.is_empty()
+        {
+            let count = search.matches.len() as isize;
+            search.current = (search.current as isize + delta).rem_euclid(count) as usize;
+            self.scroll_to_match();
+        }
+    }
+
+    /// Re-runs the search after the pattern changed and jumps to the first match from
+    /// the cursor on
+    fn search_changed(&mut self) {
+        self.refresh_search();
+        if let Some(search) = &mut self.search {
+            search.current = search
+                .matches
+                .iter()
+                .position(|m| m.line >= self.preview_cursor)
+                .unwrap_or(0);
+        }
+        self.scroll_to_match();
+    }
+
+    /// Renders more of the selected file once `line` comes within a page of the end of
+    /// what the preview holds, so the cursor can go on past the first screenfuls
+    fn load_lines_up_to(&mut self, line: usize) {
+        let Some(preview) = &self.preview else {
+            return;
+        };
+        let loaded = preview.text.lines.len();
+        let total = preview
+            .info
+            .as_ref()
+            .and_then(|info| info.lines)
+            .unwrap_or(0);
+        let page = self.curr_preview_height as usize;
+        if !preview.numbered || total <= loaded || line.saturating_add(page) < loaded {
+            return;
+        }
+        self.preview_lines = line.saturating_add(page).max(loaded * 2).min(total);
+        self.update_preview();
+    }
+
+    /// Finds the matches in the current preview, which changes with the selection
+    fn refresh_search(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.matches = match (&self.preview, &self.selected_path) {
+                (Some(preview), Some(path)) => find_matches(
+                    &preview.search_lines(path, self.preview_opts.tab_width),
+                    &search.pattern,
+                ),
+                _ => Vec::new(),
+            };
+            search.current = search.current.min(search.matches.len().saturating_sub(1));
+        }
+    }
+
+    /// Scrolls the current match into view, leaving some context above it. A match past
+    /// the part of the file rendered so far has more of it rendered first.
+    fn scroll_to_match(&mut self) {
+        let Some(found) = self
+            .search
+            .as_ref()
+            .and_then(|search| search.matches.get(search.current))
+            .copied()
+        else {
+            return;
+        };
+        self.load_lines_up_to(found.line);
+        let Some(preview) = self.preview.clone() else {
+            return;
+        };
+        // A table keeps its header row above the rows that scroll
+        let is_table = preview.table.is_some();
+        let height = (self.curr_preview_height as usize).saturating_sub(usize::from(is_table));
+        if found.line < self.preview_scroll || found.line >= self.preview_scroll + height {
+            self.preview_scroll = found.line.saturating_sub(height / 3);
+        }
+        self.preview_cursor = found.line;
+        self.cursor_moved();
+        let line = match &preview.table {
+            Some(table) => table.row_lines().get(found.line).cloned(),
+            None => preview.text.lines.get(found.line).cloned(),
+        };
+        if (is_table || !self.preview_opts.wrap)
+            && let Some(line) = line
+        {
+            // Matches in the file's text are found after any annotation in front of it
+            let offset = preview.source_offset.unwrap_or(0);
+            let shifted = SearchMatch {
+                start: found.start + offset,
+                end: found.end + offset,
+                ..found
+            };
+            let (start, end) = match_columns(&line, shifted);
+            let width = self.curr_preview_width as usize;
+            let hscroll = self.preview_hscroll as usize;
+            if start < hscroll || end > hscroll + width / 2 {
+                self.preview_hscroll = start.saturating_sub(width / 4) as u16;
+            }
+        }
+    }
+
+    /// Scrolls the preview sideways, only meaningful while wrapping is off
+    pub fn scroll_preview_horizontally(&mut self, delta: i32) {
+        if self.preview_opts.wrap {
+            return;
+        }
+        self.preview_hscroll =
+            (self.preview_hscroll as i32 + delta).clamp(0, u16::MAX as i32) as u16;
+    }
+
+    /// Re-reads the git status markers, e.g. after a file was edited
+    pub fn refresh_git_status(&mut self) {
+        self.git_status = status_markers(&self.root);
+    }
+
+    /// Swaps in a new set of candidate files, e.g. after re-rooting the search
+    pub fn reset_files(&mut self, root: PathBuf, all_files: &[PathBuf], matcher: &mut Matcher) {
+        let mut buf = Vec::new();
+        self.root = root;
+        self.refresh_git_status();
+        *matcher = Matcher::default();
+        self.query.clear();
+        self.marked.clear();
+        self.selected_idx = 0;
+        self.scroll_offset = 0;
+        self.update_filtered_files(Utf32Str::new("", &mut buf), all_files, matcher);
+        self.marks_changed();
+        self.update_preview();
+    }
+
+    /// Puts `message` in the preview pane until the preview is next loaded
+    pub fn show_error(&mut self, message: &str) {
+        self.preview = Some(Arc::new(error_preview(message)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{plain_lines, temp_dir_with};
+
+    fn state_for(files: &[PathBuf], root: &Path) -> AppState {
+        let mut matcher = Matcher::default();
+        let mut state = AppState::new(
+            root.to_path_buf(),
+            files,
+            &mut matcher,
+            PreviewOptions::for_tests(),
+            1024 * 1024,
+        );
+        state.set_preview_size(80, 20);
+        state
+    }
+
+    fn shown_text(state: &AppState) -> Vec<String> {
+        plain_lines(&state.preview.as_ref().unwrap().text.lines)
+    }
+
+    #[test]
+    fn resizing_renders_for_the_new_size() {
+        let dir = temp_dir_with(&[("a.txt", b"one\n")]);
+        let files = [dir.path().join("a.txt")];
+        let mut state = state_for(&files, dir.path());
+        let old_key = CacheKey::new(&files[0], "", 1, MAX_PREVIEW_LINES, 80, 20);
+        assert!(state.preview_cache.contains(&old_key));
+
+        state.set_preview_size(100, 30);
+        assert!(!state.preview_cache.contains(&old_key));
+        let new_key = CacheKey::new(&files[0], "", 1, MAX_PREVIEW_LINES, 100, 30);
+        assert!(state.preview_cache.contains(&new_key));
+    }
+
+    #[test]
+    fn edited_files_are_rendered_again() {
+        let dir = temp_dir_with(&[("a.txt", b"before\n")]);
+        let files = [dir.path().join("a.txt")];
+        let mut state = state_for(&files, dir.path());
+        assert_eq!(shown_text(&state), ["before"]);
+
+        // Same length and mtime, so only the invalidation can tell it changed
+        let mtime = std::fs::metadata(&files[0]).unwrap().modified().unwrap();
+        std::fs::write(&files[0], "after!\n").unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&files[0])
+            .unwrap();
+        file.set_modified(mtime).unwrap();
+        state.update_preview();
+        assert_eq!(shown_text(&state), ["before"]);
+
+        state.invalidate_previews(&files);
+        assert_eq!(shown_text(&state), ["after!"]);
+    }
+
+    #[test]
+    fn neighbours_are_prefetched_except_in_git_views() {
+        let names: Vec<String> = (0..10).map(|n| format!("{n}.txt")).collect();
+        let contents: Vec<(&str, &[u8])> = names
+            .iter()
+            .map(|name| (name.as_str(), b"x\n".as_slice()))
+            .collect();
+        let dir = temp_dir_with(&contents);
+        let files: Vec<PathBuf> = names.iter().map(|name| dir.path().join(name)).collect();
+        let mut state = state_for(&files, dir.path());
+        state.prefetcher.cancel();
+        state.preview_cache.clear();
+        state.selected_idx = 5;
+
+        let paths = |keys: Vec<CacheKey>| -> Vec<PathBuf> {
+            keys.into_iter().map(|key| key.path).collect()
+        };
+        let expected: Vec<PathBuf> = [6, 4, 7, 3, 8, 2]
+            .iter()
+            .map(|&i| state.filtered_files[i].0.clone())
+            .collect();
+        assert_eq!(paths(state.prefetch_keys(false)), expected);
+        assert_eq!(state.prefetch_keys(true).len(), 9);
+
+        for view in [GitView::History, GitView::Blame] {
+            state.preview_opts.git_view = Some(view);
+            assert!(state.prefetch_keys(true).is_empty());
+        }
+    }
+
+    #[test]
+    fn search_renders_the_file_down_to_a_late_match() {
+        let content: String = (1..=300)
+            .map(|n| match n {
+                250 => "needle\n".to_string(),
+                n => format!("line {n}\n"),
+            })
+            .collect();
+        let dir = temp_dir_with(&[("long.txt", content.as_bytes())]);
+        let files = [dir.path().join("long.txt")];
+        let mut state = state_for(&files, dir.path());
+
+        state.focus = Focus::Preview;
+        state.open_preview_search();
+        state.edit_preview_search(|pattern| pattern.push_str("needle"));
+        let search = state.search.as_ref().unwrap();
+        assert_eq!(search.matches.len(), 1);
+        assert_eq!(state.preview_cursor, 249);
+        assert!(state.preview.as_ref().unwrap().text.lines.len() > 249);
+        assert!(state.preview_scroll <= 249 && 249 < state.preview_scroll + 20);
+    }
+
+    #[test]
+    fn cursor_scrolls_the_preview_and_loads_more_lines() {
+        let content: String = (1..=MAX_PREVIEW_LINES * 3)
+            .map(|n| format!("{n}\n"))
+            .collect();
+        let dir = temp_dir_with(&[("long.txt", content.as_bytes())]);
+        let files = [dir.path().join("long.txt")];
+        let mut state = state_for(&files, dir.path());
+        state.focus = Focus::Preview;
+
+        state.move_preview_cursor(25);
+        assert_eq!(state.preview_cursor, 25);
+        assert_eq!(state.preview_scroll, 6);
+        state.move_preview_cursor(-100);
+        assert_eq!((state.preview_cursor, state.preview_scroll), (0, 0));
+
+        state.move_preview_cursor(MAX_PREVIEW_LINES as isize + 10);
+        let loaded = state.preview.as_ref().unwrap().text.lines.len();
+        assert!(loaded > MAX_PREVIEW_LINES + 10);
+        assert_eq!(state.preview_cursor, MAX_PREVIEW_LINES + 10);
+        assert_eq!(state.cursor_file_line(), Some(MAX_PREVIEW_LINES + 11));
+
+        // The cursor stops on the last line of the file
+        state.move_preview_cursor(isize::MAX);
+        assert_eq!(state.preview_cursor, MAX_PREVIEW_LINES * 3 - 1);
+    }
+
+    #[test]
+    fn yanks_the_selected_lines_as_written() {
+        let dir = temp_dir_with(&[("a.txt", b"one\n\ttwo\nthree\nfour\n")]);
+        let files = [dir.path().join("a.txt")];
+        let mut state = state_for(&files, dir.path());
+        state.focus = Focus::Preview;
+
+        assert_eq!(state.yank_selection().as_deref(), Some("one"));
+
+        // Selecting upwards from the anchor takes the same lines
+        state.move_preview_cursor(2);
+        state.toggle_selection();
+        state.move_preview_cursor(-1);
+        assert_eq!(state.selected_lines(), 1..=2);
+        assert_eq!(state.yank_selection().as_deref(), Some("\ttwo\nthree"));
+        assert_eq!(state.selection_anchor, None);
+    }
+
+    fn three_files() -> (tempfile::TempDir, Vec<PathBuf>) {
+        let dir = temp_dir_with(&[("a.txt", b"a\n"), ("b.txt", b"b\n"), ("c.txt", b"c\n")]);
+        let files = ["a.txt", "b.txt", "c.txt"].map(|name| dir.path().join(name));
+        (dir, files.to_vec())
+    }
+
+    #[test]
+    fn toggle_mark_moves_down_and_unmarks() {
+        let (dir, files) = three_files();
+        let mut state = state_for(&files, dir.path());
+        let first = state.filtered_files[0].0.clone();
+
+        state.toggle_mark();
+        assert_eq!(state.selected_idx, 1);
+        assert!(state.marked.contains(&first));
+
+        state.selected_idx = 0;
+        state.toggle_mark();
+        assert!(state.marked.is_empty());
+    }
+
+    #[test]
+    fn mark_all_and_invert() {
+        let (dir, files) = three_files();
+        let mut state = state_for(&files, dir.path());
+        state.mark_all();
+        assert_eq!(state.marked.len(), 3);
+        state.invert_marks();
+        assert!(state.marked.is_empty());
+
+        state.toggle_mark();
+        state.invert_marks();
+        assert_eq!(state.marked.len(), 2);
+    }
+
+    #[test]
+    fn chosen_paths_keep_the_collected_order() {
+        let (dir, files) = three_files();
+        let mut state = state_for(&files, dir.path());
+        assert_eq!(
+            state.chosen_paths(&files),
+            [state.filtered_files[0].0.clone()]
+        );
+
+        state.marked.insert(files[2].clone());
+        state.marked.insert(files[0].clone());
+        assert_eq!(
+            state.chosen_paths(&files),
+            [files[0].clone(), files[2].clone()]
+        );
+    }
+
+    #[test]
+    fn two_marked_files_are_compared() {
+        let (dir, files) = three_files();
+        let mut state = state_for(&files, dir.path());
+        state.toggle_mark();
+        state.toggle_mark();
+        let mut pair = [
+            state.filtered_files[0].0.clone(),
+            state.filtered_files[1].0.clone(),
+        ];
+        pair.sort();
+        assert_eq!(state.preview_opts.compare_pair, Some(pair));
+
+        state.toggle_mark();
+        assert_eq!(state.preview_opts.compare_pair, None);
     }
 }
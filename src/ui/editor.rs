@@ -0,0 +1,111 @@
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Opens `paths` in one invocation of the user's editor, with the cursor on `line` of the
+/// first file when a line is given and the editor is one whose command line syntax for
+/// that is known
+pub fn edit_files(paths: &[PathBuf], line: Option<usize>) -> io::Result<()> {
+    let Some((first, rest)) = paths.split_first() else {
+        return Ok(());
+    };
+    let (program, mut args) = editor_command()?;
+    let name = program
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match line.and_then(|line| line_args(&name, first, line)) {
+        Some(line_args) => args.extend(line_args),
+        None if rest.is_empty() => return edit::edit_file(first),
+        None => args.push(first.into()),
+    }
+    args.extend(rest.iter().map(OsString::from));
+
+    let status = Command::new(&program).args(&args).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} exited with {status}",
+            program.display()
+        )))
+    }
+}
+
+/// The editor program and the arguments it's configured with, from `$VISUAL` or
+/// `$EDITOR` or else whatever editor can be found
+fn editor_command() -> io::Result<(PathBuf, Vec<OsString>)> {
+    for var in ["VISUAL", "EDITOR"] {
+        if let Ok(value) = env::var(var) {
+            let mut words = value.split_whitespace();
+            if let Some(program) = words.next() {
+                return Ok((program.into(), words.map(OsString::from).collect()));
+            }
+        }
+    }
+    Ok((edit::get_editor()?, Vec::new()))
+}
+
+/// How the editor called `name` is told to open `path` at `line`
+fn line_args(name: &str, path: &Path, line: usize) -> Option<Vec<OsString>> {
+    let at_line = |separator: &str| {
+        let mut arg = path.as_os_str().to_os_string();
+        arg.push(format!("{separator}{line}"));
+        arg
+    };
+    match name {
+        "vi" | "vim" | "nvim" | "gvim" | "view" | "nano" | "pico" | "emacs" | "emacsclient"
+        | "micro" | "kak" | "mg" | "ne" | "joe" | "jed" => {
+            Some(vec![format!("+{line}").into(), path.into()])
+        }
+        "code" | "code-insiders" | "codium" | "cursor" => Some(vec!["--goto".into(), at_line(":")]),
+        "subl" | "hx" | "helix" | "zed" | "atom" => Some(vec![at_line(":")]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(name: &str, line: usize) -> Option<Vec<String>> {
+        line_args(name, Path::new("src/main.rs"), line).map(|args| {
+            args.into_iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn vim_style_editors_take_a_plus_line() {
+        assert_eq!(
+            args("nvim", 42),
+            Some(vec!["+42".into(), "src/main.rs".into()])
+        );
+        assert_eq!(
+            args("nano", 1),
+            Some(vec!["+1".into(), "src/main.rs".into()])
+        );
+    }
+
+    #[test]
+    fn vscode_style_editors_take_goto() {
+        assert_eq!(
+            args("code", 7),
+            Some(vec!["--goto".into(), "src/main.rs:7".into()])
+        );
+    }
+
+    #[test]
+    fn colon_suffix_editors_take_path_and_line() {
+        assert_eq!(args("hx", 3), Some(vec!["src/main.rs:3".into()]));
+        assert_eq!(args("subl", 3), Some(vec!["src/main.rs:3".into()]));
+    }
+
+    #[test]
+    fn unknown_editors_get_no_line() {
+        assert_eq!(args("ed", 3), None);
+    }
+}
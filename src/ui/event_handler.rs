@@ -1,14 +1,22 @@
 use crate::ui::appstate::{AppState, Focus};
-use crossterm::event::{Event, KeyCode};
+use crossterm::event::{Event, KeyCode, KeyModifiers};
 use nucleo::{Matcher, Utf32Str};
 use std::error::Error;
 
-pub use edit::edit_file;
+/// Columns moved per Shift+Left/Right press in the preview
+const HSCROLL_STEP: i32 = 4;
 
 pub enum AppAction {
     Quit,
     Continue,
-    EditFile(std::path::PathBuf),
+    /// Open the files in one editor, at a line of the first if one is given
+    EditFiles(Vec<std::path::PathBuf>, Option<usize>),
+    /// Put this text on the clipboard
+    Copy(String),
+    /// End a print mode session with these paths, none if nothing matched
+    Print(Vec<std::path::PathBuf>),
+    /// Restart the search rooted at this directory
+    Reroot(std::path::PathBuf),
 }
 
 pub fn handle_events(
@@ -21,6 +29,69 @@ pub fn handle_events(
     let prev_query = state.query.clone();
     let prev_selected = state.selected_idx;
     if let Event::Key(key) = event {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('t') => {
+                    state.cycle_theme();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('w') => {
+                    state.toggle_wrap();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('d') => {
+                    state.toggle_diff();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('g') => {
+                    state.cycle_git_view();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('r') => {
+                    state.toggle_structured();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('e') => {
+                    state.cycle_collapse_depth();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('x') => {
+                    state.toggle_compare_base();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('s') => {
+                    state.toggle_side_by_side();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('f') => {
+                    state.toggle_follow();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('o') => {
+                    state.toggle_metadata();
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Char('p') => {
+                    state.toggle_preview_focus();
+                    return Ok(AppAction::Continue);
+                }
+                _ => {}
+            }
+        }
+        if key.modifiers.contains(KeyModifiers::SHIFT) {
+            match key.code {
+                KeyCode::Left => {
+                    state.scroll_preview_horizontally(-HSCROLL_STEP);
+                    return Ok(AppAction::Continue);
+                }
+                KeyCode::Right => {
+                    state.scroll_preview_horizontally(HSCROLL_STEP);
+                    return Ok(AppAction::Continue);
+                }
+                _ => {}
+            }
+        }
+
         match state.focus {
             Focus::SearchBar => match key.code {
                 KeyCode::Char(c) => {
@@ -33,6 +104,9 @@ pub fn handle_events(
                     state.focus = Focus::Results;
                 }
                 KeyCode::Esc => return Ok(AppAction::Quit),
+                KeyCode::Enter if state.print_mode => {
+                    return Ok(AppAction::Print(state.chosen_paths(all_files)));
+                }
                 KeyCode::Enter => state.focus = Focus::Results,
                 _ => {}
             },
@@ -51,15 +125,82 @@ pub fn handle_events(
                         state.selected_idx = 0;
                     }
                 }
-                KeyCode::Tab => state.focus = Focus::SearchBar,
+                KeyCode::Right => {
+                    if let Some((dir, _, _)) = state.filtered_files.get(state.selected_idx)
+                        && dir.is_dir()
+                    {
+                        return Ok(AppAction::Reroot(dir.clone()));
+                    }
+                }
+                KeyCode::BackTab => state.focus = Focus::SearchBar,
                 KeyCode::Esc => return Ok(AppAction::Quit),
+                KeyCode::Tab | KeyCode::Char(' ') => state.toggle_mark(),
+                KeyCode::Char('a') => state.mark_all(),
+                KeyCode::Char('i') => state.invert_marks(),
                 KeyCode::Enter => {
-                    if let Some((edit_path, _, _)) = state.filtered_files.get(state.selected_idx) {
-                        return Ok(AppAction::EditFile(edit_path.clone()));
+                    let paths = state.chosen_paths(all_files);
+                    if state.print_mode {
+                        return Ok(AppAction::Print(paths));
+                    }
+                    if !paths.is_empty() {
+                        return Ok(AppAction::EditFiles(paths, None));
                     }
                 }
                 _ => {}
             },
+            Focus::Preview if state.search.as_ref().is_some_and(|search| search.editing) => {
+                match key.code {
+                    KeyCode::Char(c) => state.edit_preview_search(|pattern| pattern.push(c)),
+                    KeyCode::Backspace => state.edit_preview_search(|pattern| {
+                        pattern.pop();
+                    }),
+                    KeyCode::Enter => state.finish_preview_search(),
+                    KeyCode::Esc => state.close_preview_search(),
+                    _ => {}
+                }
+            }
+            Focus::Preview => {
+                let page = state.curr_preview_height.max(1) as isize;
+                match key.code {
+                    KeyCode::Char('/') => state.open_preview_search(),
+                    KeyCode::Char('n') => state.jump_to_match(1),
+                    KeyCode::Char('N') => state.jump_to_match(-1),
+                    KeyCode::Up => state.move_preview_cursor(-1),
+                    KeyCode::Down => state.move_preview_cursor(1),
+                    KeyCode::PageUp => state.move_preview_cursor(-page),
+                    KeyCode::PageDown => state.move_preview_cursor(page),
+                    KeyCode::Home => state.move_preview_cursor(isize::MIN),
+                    KeyCode::End => state.move_preview_cursor(isize::MAX),
+                    KeyCode::Char('v') => state.toggle_selection(),
+                    KeyCode::Char('y') => {
+                        if let Some(text) = state.yank_selection() {
+                            return Ok(AppAction::Copy(text));
+                        }
+                    }
+                    KeyCode::Enter if state.print_mode => {
+                        return Ok(AppAction::Print(state.chosen_paths(all_files)));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(path) = &state.selected_path {
+                            return Ok(AppAction::EditFiles(
+                                vec![path.clone()],
+                                state.cursor_file_line(),
+                            ));
+                        }
+                    }
+                    KeyCode::Tab => state.focus = Focus::Results,
+                    KeyCode::Esc => {
+                        if state.selection_anchor.is_some() {
+                            state.selection_anchor = None;
+                        } else if state.search.is_some() {
+                            state.close_preview_search();
+                        } else {
+                            state.focus = Focus::Results;
+                        }
+                    }
+                    _ => {}
+                }
+            }
         }
     }
 
@@ -87,8 +228,93 @@ pub fn handle_events(
 
     // Update preview if selection changed or query changed
     if state.selected_idx != prev_selected || state.query != prev_query {
-        state.update_preview();
+        state.refresh_preview(state.query != prev_query);
     }
 
     Ok(AppAction::Continue)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::PreviewOptions;
+    use crate::test_util::temp_dir_with;
+    use crossterm::event::KeyEvent;
+    use std::path::PathBuf;
+
+    fn press(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    /// Marks the first and last of three files with Tab and Space, then presses Enter
+    fn enter_with_two_marked(print_mode: bool) -> (AppAction, Vec<PathBuf>) {
+        let dir = temp_dir_with(&[("a.txt", b"a\n"), ("b.txt", b"b\n"), ("c.txt", b"c\n")]);
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        let mut matcher = Matcher::default();
+        let mut state = AppState::new(
+            dir.path().to_path_buf(),
+            &files,
+            &mut matcher,
+            PreviewOptions::for_tests(),
+            1024 * 1024,
+        );
+        state.print_mode = print_mode;
+        state.focus = Focus::Results;
+        let mut buf = Vec::new();
+        for code in [KeyCode::Tab, KeyCode::Down, KeyCode::Char(' ')] {
+            handle_events(press(code), &files, &mut matcher, &mut buf, &mut state).unwrap();
+        }
+        let marked = state.chosen_paths(&files);
+        let action = handle_events(
+            press(KeyCode::Enter),
+            &files,
+            &mut matcher,
+            &mut buf,
+            &mut state,
+        )
+        .unwrap();
+        (action, marked)
+    }
+
+    #[test]
+    fn enter_opens_every_marked_file() {
+        let (action, marked) = enter_with_two_marked(false);
+        assert_eq!(marked.len(), 2);
+        assert!(matches!(action, AppAction::EditFiles(paths, None) if paths == marked));
+    }
+
+    #[test]
+    fn enter_prints_every_marked_file_in_print_mode() {
+        let (action, marked) = enter_with_two_marked(true);
+        assert_eq!(marked.len(), 2);
+        assert!(matches!(action, AppAction::Print(paths) if paths == marked));
+    }
+
+    #[test]
+    fn shift_tab_returns_to_the_search_bar() {
+        let dir = temp_dir_with(&[("a.txt", b"a\n")]);
+        let files = [dir.path().join("a.txt")];
+        let mut matcher = Matcher::default();
+        let mut state = AppState::new(
+            dir.path().to_path_buf(),
+            &files,
+            &mut matcher,
+            PreviewOptions::for_tests(),
+            1024 * 1024,
+        );
+        state.focus = Focus::Results;
+        let mut buf = Vec::new();
+        handle_events(
+            press(KeyCode::BackTab),
+            &files,
+            &mut matcher,
+            &mut buf,
+            &mut state,
+        )
+        .unwrap();
+        assert!(matches!(state.focus, Focus::SearchBar));
+    }
+}
@@ -1,4 +1,5 @@
 use crossterm::{
+    clipboard::CopyToClipboard,
     event::{self},
     execute,
     terminal::{
@@ -10,14 +11,19 @@ use ratatui::{
     Terminal,
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    text::{Line, Span, Text},
     widgets::{Block, Borders},
 };
-use std::{error::Error, io, path::PathBuf};
+use std::{
+    error::Error,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
 
 // Bring in our new modules
 pub mod appstate;
+pub mod editor;
 pub mod event_handler;
 pub mod renderer;
 
@@ -25,22 +31,79 @@ pub mod renderer;
 use appstate::AppState;
 use event_handler::AppAction; // Bring in the enum from event_handler
 
-// Import highlight from the crate root
-use crate::highlight::highlight_contents;
+use crate::filesystem::FileSource;
+use crate::preview::PreviewOptions;
+use crate::preview::archive::{ArchiveMember, ExtractedMembers};
+
+/// How often a preview command running in the background is checked for having finished
+const PENDING_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often a followed file is checked for new lines
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
 
-pub fn run_app(all_files: &[PathBuf], matcher: &mut nucleo::Matcher) -> Result<(), Box<dyn Error>> {
+/// Where the UI is drawn in print mode, since stdout is kept for the result
+#[cfg(unix)]
+const TTY_PATH: &str = "/dev/tty";
+#[cfg(windows)]
+const TTY_PATH: &str = "CONOUT$";
+
+/// Leaves raw mode and the alternate screen after `run_app` failed part way, on the
+/// terminal it was drawing to
+pub fn restore_terminal(print_mode: bool) {
+    let _ = disable_raw_mode();
+    if print_mode {
+        if let Ok(mut tty) = OpenOptions::new().write(true).open(TTY_PATH) {
+            let _ = execute!(tty, LeaveAlternateScreen);
+        }
+    } else {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// How a session ended
+pub enum Outcome {
+    /// Enter in print mode, with the chosen paths and the query they were found with
+    Selected { paths: Vec<PathBuf>, query: String },
+    /// Enter in print mode with nothing matching the query
+    NoMatch { query: String },
+    /// Esc, or anything else that isn't a print mode selection
+    Aborted,
+}
+
+/// Runs the finder until it is quit. In print mode Enter ends it with the chosen paths
+/// rather than opening them, and the UI goes to the terminal directly so that stdout is
+/// free for them.
+pub fn run_app(
+    root: PathBuf,
+    mut all_files: Vec<PathBuf>,
+    source: &FileSource,
+    matcher: &mut nucleo::Matcher,
+    preview_opts: PreviewOptions,
+    cache_budget: usize,
+    print_mode: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let mut output: Box<dyn Write> = if print_mode {
+        Box::new(OpenOptions::new().write(true).open(TTY_PATH)?)
+    } else {
+        Box::new(io::stdout())
+    };
     enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, Clear(ClearType::All))?;
-    let backend = CrosstermBackend::new(stdout);
+    execute!(output, EnterAlternateScreen, Clear(ClearType::All))?;
+    let backend = CrosstermBackend::new(output);
     let mut terminal = Terminal::new(backend)?;
     let mut buf = Vec::new();
     terminal.clear()?;
 
-    let mut state = AppState::new(all_files, matcher);
+    let _extracted = ExtractedMembers;
+    let mut state = AppState::new(root, &all_files, matcher, preview_opts, cache_budget);
+    state.print_mode = print_mode;
+    let mut outcome = Outcome::Aborted;
 
     loop {
         buf.clear();
+        // Picks up Event::Resize before measuring, so the preview is re-rendered for the
+        // new pane size rather than served from the cache
+        terminal.autoresize()?;
         let size = terminal.get_frame().area();
         let vertical_chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -56,8 +119,7 @@ pub fn run_app(all_files: &[PathBuf], matcher: &mut nucleo::Matcher) -> Result<(
         let preview_block_for_calc = Block::default().borders(Borders::ALL);
         let inner_preview_area = preview_block_for_calc.inner(preview_chunk);
 
-        state.curr_preview_width = inner_preview_area.width;
-        state.curr_preview_height = inner_preview_area.height;
+        state.set_preview_size(inner_preview_area.width, inner_preview_area.height);
 
         terminal.draw(|f| {
             renderer::draw_ui(f, &mut state);
@@ -66,21 +128,82 @@ pub fn run_app(all_files: &[PathBuf], matcher: &mut nucleo::Matcher) -> Result<(
         let max_visible = terminal.size()?.height.saturating_sub(6); // 6 accounts for the borders
         // and other widgets
 
+        // A followed file is checked for new lines while waiting for a key, and the
+        // preview redrawn when it grew
+        if state.is_following() {
+            let mut grew = false;
+            while !grew && !event::poll(FOLLOW_INTERVAL)? {
+                grew = state.poll_follow();
+            }
+            if grew {
+                continue;
+            }
+        }
+        // Likewise for the selection's preview command finishing on the prefetcher
+        if state.is_pending() {
+            let mut finished = false;
+            while !finished && !event::poll(PENDING_INTERVAL)? {
+                finished = state.poll_pending();
+            }
+            if finished {
+                continue;
+            }
+        }
         let event = event::read()?;
-        match event_handler::handle_events(event, all_files, matcher, &mut buf, &mut state)? {
+        match event_handler::handle_events(event, &all_files, matcher, &mut buf, &mut state)? {
             AppAction::Quit => break,
+            AppAction::Print(paths) => {
+                let query = state.query.clone();
+                outcome = if paths.is_empty() {
+                    Outcome::NoMatch { query }
+                } else {
+                    Outcome::Selected { paths, query }
+                };
+                break;
+            }
             AppAction::Continue => (),
-            AppAction::EditFile(path) => {
+            AppAction::EditFiles(paths, line) => {
+                // Archive members are edited as a temporary copy
+                let on_disk = paths
+                    .iter()
+                    .map(|path| match ArchiveMember::from_path(path) {
+                        Some(member) => member.extract(),
+                        None => Ok(path.clone()),
+                    })
+                    .collect::<io::Result<Vec<_>>>();
+                let on_disk = match on_disk {
+                    Ok(on_disk) => on_disk,
+                    Err(e) => {
+                        state.show_error(&format!("Can't extract archive member: {e}"));
+                        continue;
+                    }
+                };
                 disable_raw_mode()?;
                 execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-                event_handler::edit_file(path)?;
+                editor::edit_files(&on_disk, line)?;
                 enable_raw_mode()?;
                 execute!(
                     terminal.backend_mut(),
                     EnterAlternateScreen,
                     Clear(ClearType::All)
                 )?;
+                state.invalidate_previews(&paths);
+                state.refresh_git_status();
+            }
+            AppAction::Copy(text) => {
+                execute!(
+                    terminal.backend_mut(),
+                    CopyToClipboard::to_clipboard_from(text)
+                )?;
             }
+            AppAction::Reroot(dir) => match source.collect(&dir.to_string_lossy()) {
+                Ok(files) => {
+                    all_files = files;
+                    state.reset_files(dir, &all_files, matcher);
+                }
+                // The search stays where it was
+                Err(e) => state.show_error(&format!("Can't search {}: {e}", dir.display())),
+            },
         }
 
         if state.selected_idx < state.scroll_offset as usize {
@@ -93,5 +216,5 @@ pub fn run_app(all_files: &[PathBuf], matcher: &mut nucleo::Matcher) -> Result<(
     // Cleanup
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    Ok(())
+    Ok(outcome)
 }
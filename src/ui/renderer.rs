@@ -1,12 +1,24 @@
-use crate::ui::appstate::{AppState, Focus};
+use crate::preview::search::highlight_ranges;
+use crate::preview::structured::TableData;
+use crate::preview::{Preview, PreviewOptions, no_preview};
+use crate::sanitize::{control_picture, sanitize, sanitize_line};
+use crate::ui::appstate::{AppState, Focus, PreviewSearch};
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
     prelude::Color,
-    style::Style,
+    style::{Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Clear as RatatuiClear, Paragraph},
+    widgets::{Block, Borders, Cell, Clear as RatatuiClear, Paragraph, Row, Table},
 };
+use std::ops::RangeInclusive;
+use unicode_width::UnicodeWidthChar;
+
+/// Background of the preview cursor line and of selected lines
+const SELECTION_BG: Color = Color::DarkGray;
+const MATCH_STYLE: Style = Style::new().fg(Color::Black).bg(Color::Yellow);
+/// The match last jumped to with n/N
+const CURRENT_MATCH_STYLE: Style = Style::new().fg(Color::Black).bg(Color::LightRed);
 
 pub fn draw_ui(f: &mut Frame<'_>, state: &mut AppState) {
     f.render_widget(RatatuiClear, f.area());
@@ -31,11 +43,6 @@ pub fn draw_ui(f: &mut Frame<'_>, state: &mut AppState) {
     f.render_widget(RatatuiClear, content_chunk);
     f.render_widget(RatatuiClear, preview_chunk);
 
-    let preview_block_for_calc = Block::default().borders(Borders::ALL);
-    let inner_preview_area = preview_block_for_calc.inner(preview_chunk);
-
-    state.curr_preview_width = inner_preview_area.width;
-    state.curr_preview_height = inner_preview_area.height;
     draw_search_bar(
         state,
         search_chunk,
@@ -49,7 +56,12 @@ pub fn draw_ui(f: &mut Frame<'_>, state: &mut AppState) {
         matches!(state.focus, Focus::Results),
     );
 
-    draw_file_preview(preview_chunk, f, state);
+    draw_file_preview(
+        preview_chunk,
+        f,
+        state,
+        matches!(state.focus, Focus::Preview),
+    );
 }
 
 fn draw_content_box(app_state: &AppState, size: Rect, f: &mut Frame<'_>, focused: bool) {
@@ -57,7 +69,7 @@ fn draw_content_box(app_state: &AppState, size: Rect, f: &mut Frame<'_>, focused
         .filtered_files
         .iter()
         .enumerate()
-        .map(|(i, (_, n, v))| {
+        .map(|(i, (p, n, v))| {
             // p = path
             // n = name
             // v = index vector
@@ -66,6 +78,24 @@ fn draw_content_box(app_state: &AppState, size: Rect, f: &mut Frame<'_>, focused
 
             let mut spans = Vec::new();
 
+            // The mark column only shows up once something is marked
+            if !app_state.marked.is_empty() {
+                let mark = if app_state.marked.contains(p) {
+                    "● "
+                } else {
+                    "  "
+                };
+                spans.push(Span::styled(mark, Style::default().fg(Color::Magenta)));
+            }
+
+            if let Some(git_status) = &app_state.git_status {
+                let marker = git_status.get(p).copied().unwrap_or(' ');
+                spans.push(Span::styled(
+                    format!("{marker} "),
+                    Style::default().fg(git_marker_color(marker)),
+                ));
+            }
+
             for (idx, ch) in n.chars().enumerate() {
                 let style = if highlights.contains(&(idx as u32)) {
                     Style::default()
@@ -74,7 +104,9 @@ fn draw_content_box(app_state: &AppState, size: Rect, f: &mut Frame<'_>, focused
                 } else {
                     Style::default()
                 };
-                spans.push(Span::styled(ch.to_string(), style));
+                // File names can contain control characters too
+                let shown = control_picture(ch).unwrap_or_else(|| ch.to_string());
+                spans.push(Span::styled(shown, style));
             }
 
             if i == app_state.selected_idx {
@@ -91,7 +123,10 @@ fn draw_content_box(app_state: &AppState, size: Rect, f: &mut Frame<'_>, focused
             }
         })
         .collect();
-    let title_text = format!("Results ({})", app_state.filtered_files.len());
+    let mut title_text = format!("Results ({})", app_state.filtered_files.len());
+    if !app_state.marked.is_empty() {
+        title_text.push_str(&format!(" · {} marked", app_state.marked.len()));
+    }
 
     let content_box = Paragraph::new(Text::from(line_of_content))
         .block(
@@ -108,6 +143,17 @@ fn draw_content_box(app_state: &AppState, size: Rect, f: &mut Frame<'_>, focused
     f.render_widget(content_box, size);
 }
 
+/// Colors `git status` letters the way `git status --short` does
+fn git_marker_color(marker: char) -> Color {
+    match marker {
+        'A' => Color::Green,
+        'M' | 'R' => Color::Yellow,
+        'D' | 'U' => Color::Red,
+        '?' => Color::Magenta,
+        _ => Color::DarkGray,
+    }
+}
+
 fn draw_search_bar(app_state: &AppState, size: Rect, f: &mut Frame, focused: bool) {
     let input_hint = "Type your query here";
 
@@ -128,38 +174,360 @@ fn draw_search_bar(app_state: &AppState, size: Rect, f: &mut Frame, focused: boo
     f.render_widget(search_box, size);
 }
 
-fn draw_file_preview(area: Rect, f: &mut Frame<'_>, app_state: &AppState) {
-    let text = app_state
-        .selected_path
-        .as_ref()
-        .and_then(|path| app_state.preview_cache.get(path).cloned())
-        .unwrap_or_else(|| {
-            let mut lines = Vec::new();
-            lines.push(ratatui::text::Line::from(ratatui::text::Span::styled(
-                "No Preview available",
-                Style::default().fg(Color::DarkGray),
-            )));
-            while (lines.len() as u16) < app_state.curr_preview_height {
-                lines.push(ratatui::text::Line::from(ratatui::text::Span::styled(
-                    " ".repeat(app_state.curr_preview_width as usize),
-                    Style::default(),
-                )));
-            }
-            Text::from(lines)
-        });
+fn draw_file_preview(area: Rect, f: &mut Frame<'_>, app_state: &AppState, focused: bool) {
+    let fallback = no_preview();
+    let preview = app_state.preview.as_deref().unwrap_or(&fallback);
 
     let path_title = if let Some(path_name) = &app_state.selected_path {
-        path_name.to_string_lossy().into_owned()
+        let mut title = sanitize(&path_name.to_string_lossy()).into_owned();
+        if let Some(label) = preview.label {
+            title.push_str(&format!(" [{label}]"));
+        }
+        if let Some(encoding) = preview.encoding {
+            title.push_str(&format!(" [{}]", encoding.name()));
+        }
+        if let Some(search) = &app_state.search
+            && !search.pattern.is_empty()
+        {
+            if search.matches.is_empty() {
+                title.push_str(" [no matches]");
+            } else {
+                title.push_str(&format!(
+                    " [{}/{}]",
+                    search.current + 1,
+                    search.matches.len()
+                ));
+            }
+        }
+        title
     } else {
         "No directory selected".to_string()
     };
 
-    let preview = Paragraph::new(text).block(
-        Block::default()
-            .title(path_title)
-            .borders(Borders::ALL)
-            .style(Style::default().fg(Color::Gray).bg(Color::Reset)),
+    let mut block = Block::default()
+        .title(path_title)
+        .title_bottom(Line::from(app_state.preview_opts.theme.as_str()).right_aligned())
+        .borders(Borders::ALL)
+        .border_style(if focused {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        })
+        .style(Style::default().fg(Color::Gray).bg(Color::Reset));
+    if let Some(search) = &app_state.search
+        && (search.editing || !search.pattern.is_empty())
+    {
+        let cursor = if search.editing { "█" } else { "" };
+        block = block.title_bottom(
+            Line::from(format!("/{}{cursor}", sanitize(&search.pattern))).left_aligned(),
+        );
+    }
+    let mut inner = block.inner(area);
+    f.render_widget(block, area);
+
+    if app_state.preview_opts.show_metadata
+        && let Some(info) = &preview.info
+    {
+        let header = info.header_lines();
+        let header_height = (header.len() as u16 + 1).min(inner.height);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(header_height), Constraint::Min(0)])
+            .split(inner);
+
+        let header_box = Paragraph::new(Text::from(header))
+            .block(Block::default().borders(Borders::BOTTOM))
+            .style(Style::default().fg(Color::Gray));
+        f.render_widget(header_box, chunks[0]);
+        inner = chunks[1];
+    }
+
+    if let Some(notice) = &preview.notice {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner);
+        let notice_line = Paragraph::new(sanitize(notice).into_owned())
+            .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD));
+        f.render_widget(notice_line, chunks[0]);
+        inner = chunks[1];
+    }
+
+    if let Some(table) = &preview.table {
+        let table = layout_table(
+            table,
+            app_state.preview_hscroll,
+            app_state.preview_scroll,
+            app_state.search.as_ref(),
+        );
+        f.render_widget(table, inner);
+        return;
+    }
+
+    let lines = layout_preview(
+        preview,
+        &app_state.preview_opts,
+        inner.width,
+        inner.height,
+        app_state.preview_hscroll,
+        app_state.preview_scroll,
+        app_state.search.as_ref(),
+        focused.then(|| app_state.selected_lines()),
     );
+    let mut paragraph = Paragraph::new(Text::from(lines));
+    if let Some(bg) = preview.text.style.bg {
+        paragraph = paragraph.style(Style::default().bg(bg));
+    }
+
+    f.render_widget(paragraph, inner);
+}
+
+/// Turns a cached preview into the rows actually shown: adds the line number gutter and
+/// either wraps long lines or cuts them to the visible, horizontally scrolled window.
+/// Every line goes through the sanitizer here, so no control byte from a file can reach
+/// the terminal whichever previewer produced it. The cursor line or selected range and the
+/// search matches are highlighted on top of the syntax colors.
+#[allow(clippy::too_many_arguments)]
+fn layout_preview(
+    preview: &Preview,
+    opts: &PreviewOptions,
+    width: u16,
+    height: u16,
+    hscroll: u16,
+    scroll: usize,
+    search: Option<&PreviewSearch>,
+    selected: Option<RangeInclusive<usize>>,
+) -> Vec<Line<'static>> {
+    let gutter_digits = if preview.numbered && opts.line_numbers {
+        preview.text.lines.len().max(1).to_string().len()
+    } else {
+        0
+    };
+    // "<number> │ "
+    let gutter_width = if gutter_digits > 0 {
+        gutter_digits + 3
+    } else {
+        0
+    };
+    let content_width = (width as usize).saturating_sub(gutter_width).max(1);
+    let gutter_style = Style::default().fg(Color::DarkGray);
+
+    let mut rows = Vec::new();
+    for (i, line) in preview.text.lines.iter().enumerate().skip(scroll) {
+        if rows.len() >= height as usize {
+            break;
+        }
+        let mut line = sanitize_line(line);
+        if selected.as_ref().is_some_and(|range| range.contains(&i)) {
+            for span in &mut line.spans {
+                span.style = span.style.bg(SELECTION_BG);
+            }
+            line.style = line.style.bg(SELECTION_BG);
+        }
+        if search.is_some() {
+            let offset = preview.source_offset.unwrap_or(0);
+            line = highlight_ranges(&line, &match_ranges(search, i, offset));
+        }
+        let line = &line;
+
+        let chunks = if opts.wrap {
+            wrap_line(line, content_width)
+        } else {
+            vec![slice_line(line, hscroll as usize, content_width)]
+        };
+
+        for (chunk, content) in chunks.into_iter().enumerate() {
+            let mut spans = Vec::new();
+            if gutter_digits > 0 {
+                let number = if chunk == 0 {
+                    format!("{:>gutter_digits$} │ ", i + 1)
+                } else {
+                    format!("{:>gutter_digits$} │ ", "")
+                };
+                spans.push(Span::styled(number, gutter_style));
+            }
+            spans.extend(content);
+            rows.push(Line::from(spans).style(line.style));
+        }
+    }
+    rows
+}
+
+/// Breaks a line into rows of at most `width` columns. A wide character that doesn't fit
+/// at the end of a row moves to the next one rather than being split.
+fn wrap_line(line: &Line<'_>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut column = 0;
+    for span in &line.spans {
+        let mut content = String::new();
+        for ch in span.content.chars() {
+            let ch_width = ch.width().unwrap_or(0);
+            if column + ch_width > width && column > 0 {
+                if !content.is_empty() {
+                    row.push(Span::styled(std::mem::take(&mut content), span.style));
+                }
+                rows.push(std::mem::take(&mut row));
+                column = 0;
+            }
+            content.push(ch);
+            column += ch_width;
+        }
+        if !content.is_empty() {
+            row.push(Span::styled(content, span.style));
+        }
+    }
+    rows.push(row);
+    rows
+}
+
+/// The character ranges of the search matches on line `i`, moved right by `offset`
+/// characters of annotation and styled by whether they are the current match
+fn match_ranges(
+    search: Option<&PreviewSearch>,
+    i: usize,
+    offset: usize,
+) -> Vec<(usize, usize, Style)> {
+    let Some(search) = search else {
+        return Vec::new();
+    };
+    search
+        .matches
+        .iter()
+        .enumerate()
+        .filter(|(_, found)| found.line == i)
+        .map(|(n, found)| {
+            let style = if n == search.current {
+                CURRENT_MATCH_STYLE
+            } else {
+                MATCH_STYLE
+            };
+            (found.start + offset, found.end + offset, style)
+        })
+        .collect()
+}
+
+/// Builds the table widget for a CSV/TSV preview, starting `scroll` rows down and with
+/// search matches highlighted in their cells. Horizontal scrolling moves by whole
+/// columns: every column that ends before the scroll offset is left out.
+fn layout_table(
+    table: &TableData,
+    hscroll: u16,
+    scroll: usize,
+    search: Option<&PreviewSearch>,
+) -> Table<'static> {
+    let mut skip = 0;
+    let mut end = 0;
+    for &width in &table.widths {
+        end += width as usize + 1;
+        if end > hscroll as usize {
+            break;
+        }
+        skip += 1;
+    }
+    let skip = skip.min(table.widths.len().saturating_sub(1));
+
+    // Match ranges are over the whole laid out row, cells being separated by one space
+    let row = |cells: Vec<String>, ranges: Vec<(usize, usize, Style)>| {
+        let mut start = 0;
+        let cells: Vec<Line<'static>> = cells
+            .into_iter()
+            .map(|cell| {
+                let len = cell.chars().count();
+                let in_cell: Vec<_> = ranges
+                    .iter()
+                    .filter_map(|&(from, to, style)| {
+                        let (from, to) = (from.max(start), to.min(start + len));
+                        (from < to).then(|| (from - start, to - start, style))
+                    })
+                    .collect();
+                start += len + 1;
+                highlight_ranges(&Line::from(cell), &in_cell)
+            })
+            .collect();
+        Row::new(cells.into_iter().skip(skip).map(Cell::from))
+    };
+    let widths = table.widths[skip..]
+        .iter()
+        .map(|&width| Constraint::Length(width));
+    let rows = table
+        .rows
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .map(|(i, cells)| row(table.laid_out(cells), match_ranges(search, i, 0)));
+
+    Table::new(rows.collect::<Vec<_>>(), widths)
+        .header(
+            row(table.laid_out(&table.header), Vec::new())
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .column_spacing(1)
+}
+
+/// The spans of `line` that fall within display columns `start..start + len`.
+/// Wide characters straddling either edge are dropped rather than split.
+fn slice_line(line: &Line<'_>, start: usize, len: usize) -> Vec<Span<'static>> {
+    let end = start + len;
+    let mut column = 0;
+    let mut sliced = Vec::new();
+
+    for span in &line.spans {
+        let mut content = String::new();
+        for ch in span.content.chars() {
+            let ch_width = ch.width().unwrap_or(0);
+            if column >= start && column + ch_width <= end {
+                content.push(ch);
+            }
+            column += ch_width;
+        }
+        if !content.is_empty() {
+            sliced.push(Span::styled(content, span.style));
+        }
+        if column >= end {
+            break;
+        }
+    }
+    sliced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::plain_lines;
+
+    fn text_of(rows: Vec<Vec<Span<'static>>>) -> Vec<String> {
+        plain_lines(&rows.into_iter().map(Line::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn wraps_at_the_width_and_keeps_styles() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let line = Line::from(vec![Span::raw("abcd"), Span::styled("efgh", bold)]);
+        let rows = wrap_line(&line, 3);
+        assert_eq!(text_of(rows.clone()), ["abc", "def", "gh"]);
+        assert_eq!(rows[1][1].style, bold);
+    }
+
+    #[test]
+    fn wide_characters_move_to_the_next_row() {
+        let line = Line::from("ab漢字");
+        assert_eq!(text_of(wrap_line(&line, 3)), ["ab", "漢", "字"]);
+        assert_eq!(text_of(wrap_line(&Line::from(""), 3)), [""]);
+    }
+
+    #[test]
+    fn slices_by_display_column() {
+        let line = Line::from(vec![Span::raw("hello "), Span::raw("world")]);
+        assert_eq!(text_of(vec![slice_line(&line, 3, 5)]), ["lo wo"]);
+        assert_eq!(text_of(vec![slice_line(&line, 20, 5)]), [""]);
+    }
 
-    f.render_widget(preview, area);
+    #[test]
+    fn wide_characters_on_the_edge_are_dropped() {
+        let line = Line::from("a漢字b");
+        // 漢 covers columns 1-2, so starting at 2 cuts it in half
+        assert_eq!(text_of(vec![slice_line(&line, 2, 4)]), ["字b"]);
+        assert_eq!(text_of(vec![slice_line(&line, 0, 4)]), ["a漢"]);
+    }
 }